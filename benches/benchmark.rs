@@ -1,4 +1,4 @@
-use brot_rs::algorithms::mandelbrot::map_to_complex_plane;
+use brot_rs::algorithms::mandelbrot::ViewTransform;
 use brot_rs::algorithms::naive_cpu::{generate_image, mandelbrot};
 use brot_rs::algorithms::{coloring::calculate_pixel_color, mandelbrot::FractalProperties};
 use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
@@ -32,12 +32,12 @@ fn cpu_benchmark(c: &mut Criterion) {
 fn coloring_benchmark(c: &mut Criterion) {
     let fp = FractalProperties::default();
     let (width, height) = (1920, 1080);
+    let transform = ViewTransform::new(&fp, width as f64, height as f64);
     let samples = (0..width * height)
         .map(|i| {
             let x = i % width;
             let y = i % width;
-            let cx = map_to_complex_plane(x as f64, width as f64, fp.center_x, fp.zoom);
-            let cy = map_to_complex_plane(y as f64, height as f64, fp.center_y, fp.zoom);
+            let (cx, cy) = transform.to_complex(x as f64, y as f64);
             let c = Complex::<f64>::new(cx, cy);
             mandelbrot(c, fp.max_iter)
         })
@@ -4,7 +4,12 @@ use prisma::{FromColor, Hsv, Rgb};
 use super::mandelbrot::FractalProperties;
 
 pub fn calculate_pixel_color(fp: FractalProperties, n: f64) -> [u8; 3] {
-    let mut deg = 0.90 + fp.color_offset * n;
+    calculate_pixel_color_cycled(fp, n, 0.0)
+}
+
+/// Colour of a pixel with the hue of the palette rotated by `phase` degrees
+pub fn calculate_pixel_color_cycled(fp: FractalProperties, n: f64, phase: f64) -> [u8; 3] {
+    let mut deg = 0.90 + fp.color_offset * n + phase;
     while deg > 360.0 {
         deg -= 360.0;
     }
@@ -1,11 +1,15 @@
+use std::str::FromStr;
+
 #[cfg(feature = "opencl")]
 use ocl::OclPrm;
+use serde::{Deserialize, Serialize};
 
 pub type Float = f64;
 
 const DEFAULT_MAX_ITER: Float = 180.0;
 
-#[derive(Copy, Clone, PartialEq, Debug)]
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default)]
 #[repr(C)]
 pub struct FractalProperties {
     pub center_x: Float,
@@ -15,6 +19,12 @@ pub struct FractalProperties {
     pub ss_factor: i32,
     pub color_offset: Float,
     pub color_saturation: Float,
+    /// Counter-clockwise rotation of the view in degrees
+    pub rotation: Float,
+    /// Horizontal shear, `x` is offset by `skew * y`
+    pub skew: Float,
+    /// Horizontal scale relative to the vertical one
+    pub stretch: Float,
 }
 
 #[cfg(feature = "opencl")]
@@ -30,10 +40,52 @@ impl Default for FractalProperties {
             ss_factor: 1,
             color_offset: 10.0,
             color_saturation: 0.6,
+            rotation: 0.0,
+            skew: 0.0,
+            stretch: 1.0,
         }
     }
 }
 
+/// Largest iteration count [`FractalProperties::validate`] accepts
+pub const MAX_ITERATIONS: Float = 1e8;
+
+/// Largest supersampling factor [`FractalProperties::validate`] accepts, 256 samples per pixel
+pub const MAX_SS_FACTOR: i32 = 16;
+
+impl FractalProperties {
+    /// Check that the view can be rendered, every front end calls this on views coming from
+    /// users before rendering them.
+    pub fn validate(&self) -> Result<(), String> {
+        if !(self.zoom.is_finite() && self.zoom > 0.0) {
+            return Err(format!("Zoom must be positive, not {}", self.zoom));
+        }
+        if !(1..=MAX_SS_FACTOR).contains(&self.ss_factor) {
+            return Err(format!(
+                "Supersampling factor must be between 1 and {}, not {}",
+                MAX_SS_FACTOR, self.ss_factor
+            ));
+        }
+        if !(self.max_iter >= 1.0 && self.max_iter <= MAX_ITERATIONS) {
+            return Err(format!(
+                "Iterations must be between 1 and {}, not {}",
+                MAX_ITERATIONS, self.max_iter
+            ));
+        }
+        let view = [
+            self.center_x,
+            self.center_y,
+            self.rotation,
+            self.skew,
+            self.stretch,
+        ];
+        if !view.iter().all(|v| v.is_finite()) {
+            return Err("Center, rotation, skew and stretch must be finite".to_string());
+        }
+        Ok(())
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum AlgorithmType {
     NaiveCPU,
@@ -41,12 +93,79 @@ pub enum AlgorithmType {
     OpenCL,
 }
 
-/// Convert a given dimension onto the complex plane the following way:
-/// - Map the position -> `[0;1]`
-/// - Offset the range by `-0.5` essentially centering it (the center becomes 0) -> `[-0.5;0.5]`
-/// - Expand the range -> `[-1;1]`
-/// - Scale the range with the given zoom -> `[-1 / zoom;1 / zoom]`
-/// - Offset the range with the given center point (move the center) -> `[center + (-1 / zoom);center + (1 / zoom)]`
-pub fn map_to_complex_plane(n: Float, max_n: Float, center: Float, zoom: Float) -> Float {
-    center + (((n / max_n) - 0.5) * 2 as Float / zoom)
+impl FromStr for AlgorithmType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "cpu" | "naivecpu" => Ok(AlgorithmType::NaiveCPU),
+            #[cfg(feature = "opencl")]
+            "opencl" => Ok(AlgorithmType::OpenCL),
+            _ => Err(format!("Unknown algorithm: {}", s)),
+        }
+    }
+}
+
+/// Affine transform from pixel coordinates to the complex plane: `c = matrix * (x, y) + offset`.
+///
+/// The image is centered on `center`, the shorter side spans `[-1 / zoom;1 / zoom]` so that
+/// pixels stay square, then the view is stretched, skewed and rotated around the center.
+#[derive(Copy, Clone, PartialEq, Debug)]
+#[repr(C)]
+pub struct ViewTransform {
+    pub matrix: [[Float; 2]; 2],
+    pub offset: [Float; 2],
+}
+
+#[cfg(feature = "opencl")]
+unsafe impl OclPrm for ViewTransform {}
+
+impl Default for ViewTransform {
+    fn default() -> Self {
+        Self::new(&FractalProperties::default(), 1 as Float, 1 as Float)
+    }
+}
+
+impl ViewTransform {
+    pub fn new(fp: &FractalProperties, width: Float, height: Float) -> Self {
+        let scale = 2 as Float / (fp.zoom * width.min(height));
+        let (sin, cos) = fp.rotation.to_radians().sin_cos();
+        // Rotation * shear/stretch, scaled from pixels to the complex plane
+        let matrix = [
+            [cos * fp.stretch * scale, (cos * fp.skew - sin) * scale],
+            [sin * fp.stretch * scale, (sin * fp.skew + cos) * scale],
+        ];
+        let (hw, hh) = (width / 2 as Float, height / 2 as Float);
+        let offset = [
+            fp.center_x - matrix[0][0] * hw - matrix[0][1] * hh,
+            fp.center_y - matrix[1][0] * hw - matrix[1][1] * hh,
+        ];
+        Self { matrix, offset }
+    }
+
+    /// Map a pixel position onto the complex plane
+    pub fn to_complex(&self, x: Float, y: Float) -> (Float, Float) {
+        let m = &self.matrix;
+        (
+            m[0][0] * x + m[0][1] * y + self.offset[0],
+            m[1][0] * x + m[1][1] * y + self.offset[1],
+        )
+    }
+
+    /// Map a point of the complex plane back to a pixel position
+    pub fn to_pixel(&self, cx: Float, cy: Float) -> (Float, Float) {
+        let m = &self.matrix;
+        let det = m[0][0] * m[1][1] - m[0][1] * m[1][0];
+        let (dx, dy) = (cx - self.offset[0], cy - self.offset[1]);
+        (
+            (m[1][1] * dx - m[0][1] * dy) / det,
+            (m[0][0] * dy - m[1][0] * dx) / det,
+        )
+    }
+}
+
+/// Approximate number of iterations done for the given pixels, including every supersample
+pub fn total_iterations(iterations: &[Float], fp: FractalProperties) -> u64 {
+    let samples = (fp.ss_factor * fp.ss_factor) as Float;
+    (iterations.iter().sum::<Float>() * samples) as u64
 }
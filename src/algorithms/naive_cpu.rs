@@ -1,13 +1,13 @@
 use num_complex::Complex;
 use rayon::prelude::*;
-use std::ops::Range;
 
 use super::{
     coloring::calculate_pixel_color,
-    mandelbrot::{map_to_complex_plane, Float, FractalProperties},
+    mandelbrot::{total_iterations, Float, FractalProperties, ViewTransform},
 };
 
-const PIXEL_CHUNK: u32 = 10000;
+/// Side length of the square tiles the image is split into
+const TILE_SIZE: u32 = 64;
 
 pub fn mandelbrot(c: Complex<Float>, max_iter: Float) -> Float {
     let mut z = Complex::new(0 as Float, 0 as Float);
@@ -22,52 +22,102 @@ pub fn mandelbrot(c: Complex<Float>, max_iter: Float) -> Float {
     n
 }
 
-fn calculate_region(
-    mut pixel_range: Range<u32>,
+fn calculate_tile(
+    tile_x: u32,
+    tile_y: u32,
+    tile_width: u32,
+    tile_height: u32,
     max_x: u32,
     max_y: u32,
     fp: FractalProperties,
-) -> Vec<[u8; 3]> {
-    let mut pixels: Vec<[u8; 3]> = Vec::new();
-    for i in &mut pixel_range {
-        let x = i % max_x;
-        let y = i / max_x;
-        pixels.push(calculate_pixel(x as Float, y as Float, max_x, max_y, fp));
+) -> Vec<Float> {
+    let transform = ViewTransform::new(&fp, max_x as Float, max_y as Float);
+    let mut iterations: Vec<Float> = Vec::with_capacity((tile_width * tile_height) as usize);
+    for y in tile_y..tile_y + tile_height {
+        for x in tile_x..tile_x + tile_width {
+            iterations.push(calculate_pixel(x as Float, y as Float, &transform, fp));
+        }
     }
-    pixels
+    iterations
 }
 
 pub fn generate_image(width: u32, height: u32, fp: FractalProperties) -> Vec<[u8; 3]> {
-    let total_pixels = width * height;
+    generate_image_tiled(width, height, fp, |_, _, _, _, _, _| {}).0
+}
+
+/// Generate the image tile by tile. Every finished tile is handed to `on_tile` as
+/// `(x, y, width, height, pixels, iterations)` before the assembled image is returned
+/// together with the iteration count of every pixel.
+pub fn generate_image_tiled<F>(
+    width: u32,
+    height: u32,
+    fp: FractalProperties,
+    on_tile: F,
+) -> (Vec<[u8; 3]>, Vec<Float>)
+where
+    F: Fn(u32, u32, u32, u32, &[[u8; 3]], u64) + Sync,
+{
+    let tiles_x = (width + TILE_SIZE - 1) / TILE_SIZE;
+    let tiles_y = (height + TILE_SIZE - 1) / TILE_SIZE;
 
-    let regions: Vec<[u8; 3]> = (0..total_pixels)
+    let tiles: Vec<_> = (0..tiles_x * tiles_y)
         .into_par_iter()
-        .step_by(PIXEL_CHUNK as usize)
-        .map(|start| {
-            let end = total_pixels.min(start + PIXEL_CHUNK);
-            calculate_region(start..end, width, height, fp)
+        .map(|i| {
+            let x = (i % tiles_x) * TILE_SIZE;
+            let y = (i / tiles_x) * TILE_SIZE;
+            let w = TILE_SIZE.min(width - x);
+            let h = TILE_SIZE.min(height - y);
+            let iterations = calculate_tile(x, y, w, h, width, height, fp);
+            let pixels: Vec<[u8; 3]> = iterations
+                .iter()
+                .map(|n| calculate_pixel_color(fp, *n))
+                .collect();
+            on_tile(x, y, w, h, &pixels, total_iterations(&iterations, fp));
+            (x, y, w, h, iterations, pixels)
         })
-        .flatten()
         .collect();
-    regions
+
+    let mut img = vec![[0u8; 3]; (width * height) as usize];
+    let mut buffer = vec![0 as Float; (width * height) as usize];
+    for (x, y, w, h, iterations, pixels) in tiles {
+        for row in 0..h {
+            let src = (row * w) as usize..((row + 1) * w) as usize;
+            let dst = ((y + row) * width + x) as usize..((y + row) * width + x + w) as usize;
+            img[dst.clone()].copy_from_slice(&pixels[src.clone()]);
+            buffer[dst].copy_from_slice(&iterations[src]);
+        }
+    }
+    (img, buffer)
+}
+
+/// Calculate the iteration counts of the `(x, y, w, h)` region of a `width`x`height` image,
+/// without calculating the rest of the image.
+pub fn generate_region(
+    (x, y, w, h): (u32, u32, u32, u32),
+    width: u32,
+    height: u32,
+    fp: FractalProperties,
+) -> Vec<Float> {
+    (y..y + h)
+        .into_par_iter()
+        .flat_map_iter(|row| calculate_tile(x, row, w, 1, width, height, fp))
+        .collect()
 }
 
-fn calculate_pixel(x: Float, y: Float, max_x: u32, max_y: u32, fp: FractalProperties) -> [u8; 3] {
+/// Calculate the supersampled, averaged iteration count of a single pixel
+fn calculate_pixel(x: Float, y: Float, transform: &ViewTransform, fp: FractalProperties) -> Float {
     // Supersample the image with the given supersample factor
     let mut vec: Vec<Float> = vec![];
     for u in 0..fp.ss_factor {
         for v in 0..fp.ss_factor {
             let x = x as Float + u as Float / fp.ss_factor as Float;
             let y = y as Float + v as Float / fp.ss_factor as Float;
-            let cx = map_to_complex_plane(x as Float, max_x as Float, fp.center_x, fp.zoom);
-            let cy = map_to_complex_plane(y as Float, max_y as Float, fp.center_y, fp.zoom);
+            let (cx, cy) = transform.to_complex(x, y);
             let c = Complex::<Float>::new(cx, cy);
             let n = mandelbrot(c, fp.max_iter);
             vec.push(n);
         }
     }
 
-    let n: f64 = vec.iter().sum::<f64>() / vec.len() as f64;
-
-    calculate_pixel_color(fp, n)
+    vec.iter().sum::<f64>() / vec.len() as f64
 }
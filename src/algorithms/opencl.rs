@@ -1,11 +1,11 @@
 use std::time::Instant;
 
 use ocl::{Buffer, Kernel, ProQue, SpatialDims};
-use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
 
 use crate::algorithms::coloring::calculate_pixel_color;
 
-use super::mandelbrot::FractalProperties;
+use super::mandelbrot::{total_iterations, FractalProperties, ViewTransform};
 
 const MANDELBROT_SRC: &str = r#"
 struct FractalProperties {
@@ -16,20 +16,24 @@ struct FractalProperties {
     int ss_factor;
     double color_offset;
     double color_saturation;
+    double rotation;
+    double skew;
+    double stretch;
 };  
 
-double map_to_complex_plane(double n, double max_n, double center, double zoom) {
-    return center + (((n / max_n) - 0.5) * 2.0 / zoom);
-}
+struct ViewTransform {
+    double matrix[2][2];
+    double offset[2];
+};
 
-__kernel void mandelbrot(struct FractalProperties fp, __global double* buffer) {
+__kernel void mandelbrot(struct FractalProperties fp, struct ViewTransform t, uint width, uint height, __global double* buffer) {
     double n = 0.0;
     for(int x_offset = 0; x_offset < fp.ss_factor; x_offset++) {
         for(int y_offset = 0; y_offset < fp.ss_factor; y_offset++) {
-            double x0 = (double)get_global_id(0) + (double)x_offset / (double)fp.ss_factor;
-            double y0 = (double)get_global_id(1) + (double)y_offset / (double)fp.ss_factor;
-            x0 = map_to_complex_plane(x0, get_global_size(0), fp.center_x, fp.zoom);
-            y0 = map_to_complex_plane(y0, get_global_size(1), fp.center_y, fp.zoom);
+            double px = (double)get_global_id(0) + (double)x_offset / (double)fp.ss_factor;
+            double py = (double)get_global_id(1) + (double)y_offset / (double)fp.ss_factor;
+            double x0 = t.matrix[0][0] * px + t.matrix[0][1] * py + t.offset[0];
+            double y0 = t.matrix[1][0] * px + t.matrix[1][1] * py + t.offset[1];
             double x = 0.0;
             double y = 0.0;
             double iteration = 0.0;
@@ -47,14 +51,19 @@ __kernel void mandelbrot(struct FractalProperties fp, __global double* buffer) {
         }    
     }
     double avg = n / pown((double)fp.ss_factor, 2);
-    buffer[get_global_id(1) * get_global_size(0) + get_global_id(0)] = avg;
+    buffer[get_global_id(1) * width + get_global_id(0)] = avg;
 }
 "#;
 
+/// Number of rows enqueued at once, every band is streamed back as a separate tile
+const BAND_HEIGHT: u32 = 64;
+
 pub struct OpenCLRenderer {
     pro_que: Option<ProQue>,
     kernel: Option<Kernel>,
     buffer: Option<Buffer<f64>>,
+    /// Image size the `pro_que` dims and the buffer are currently set up for
+    size: (u32, u32),
 }
 
 impl Default for OpenCLRenderer {
@@ -63,6 +72,7 @@ impl Default for OpenCLRenderer {
             pro_que: None,
             kernel: None,
             buffer: None,
+            size: (0, 0),
         }
     }
 }
@@ -74,48 +84,125 @@ impl OpenCLRenderer {
         height: u32,
         fp: FractalProperties,
     ) -> Result<Vec<[u8; 3]>, String> {
-        let build_timer = Instant::now();
-        // Width and height changed, rebuild needed
-        if self.pro_que.is_none()
-            || self.pro_que.as_ref().unwrap().dims().to_len() != width as usize * height as usize
-        {
-            println!("Rebuilt OpenCL pro_que");
-            self.build(width, height)
-                .expect("Failed building opencl pro_que");
-        }
+        Ok(self
+            .generate_image_tiled(width, height, fp, |_, _, _, _, _, _| {})?
+            .0)
+    }
 
-        // Set opencl kernel args
-        self.kernel.as_mut().unwrap().set_arg(0i32, fp)?;
-        self.kernel
-            .as_mut()
-            .unwrap()
-            .set_arg(1i32, self.buffer.as_ref().unwrap())?;
-        println!("Elapsed build: {}ms", build_timer.elapsed().as_millis());
+    /// Generate the image in horizontal bands. Every finished band is handed to `on_tile` as
+    /// `(x, y, width, height, pixels, iterations)` before the assembled image is returned
+    /// together with the iteration count of every pixel.
+    pub fn generate_image_tiled<F>(
+        &mut self,
+        width: u32,
+        height: u32,
+        fp: FractalProperties,
+        on_tile: F,
+    ) -> Result<(Vec<[u8; 3]>, Vec<f64>), String>
+    where
+        F: Fn(u32, u32, u32, u32, &[[u8; 3]], u64),
+    {
+        self.prepare(width, height, fp)?;
+        let kernel = self.kernel.as_ref().unwrap();
+        let buffer = self.buffer.as_ref().unwrap();
 
         let inner_timer = Instant::now();
-        unsafe {
-            self.kernel.as_ref().unwrap().enq()?;
-        }
+        let mut img: Vec<[u8; 3]> = Vec::with_capacity(width as usize * height as usize);
+        let mut iterations: Vec<f64> = Vec::with_capacity(width as usize * height as usize);
+        for y in (0..height).step_by(BAND_HEIGHT as usize) {
+            let band_height = BAND_HEIGHT.min(height - y);
+            unsafe {
+                kernel
+                    .cmd()
+                    .global_work_offset((0, y as usize))
+                    .global_work_size((width as usize, band_height as usize))
+                    .enq()?;
+            }
 
-        let mut vec = vec![0.0f64; self.buffer.as_ref().unwrap().len()];
-        self.buffer.as_ref().unwrap().read(&mut vec).enq()?;
-        println!("Elapsed inner: {}ms", inner_timer.elapsed().as_millis());
-
-        let coloring_timer = Instant::now();
-        let img: Vec<[u8; 3]> = (0..(width as usize * height as usize))
-            .into_par_iter()
-            .map(|i| {
-                let x = i % width as usize;
-                let y = i / width as usize;
-                let n = vec[y as usize * width as usize + x as usize];
-                calculate_pixel_color(fp, n)
-            })
-            .collect();
+            let mut vec = vec![0.0f64; width as usize * band_height as usize];
+            buffer
+                .read(&mut vec)
+                .offset(y as usize * width as usize)
+                .enq()?;
+
+            let band: Vec<[u8; 3]> = vec
+                .par_iter()
+                .map(|n| calculate_pixel_color(fp, *n))
+                .collect();
+            on_tile(0, y, width, band_height, &band, total_iterations(&vec, fp));
+            img.extend_from_slice(&band);
+            iterations.extend_from_slice(&vec);
+        }
         println!(
-            "Elapsed coloring: {}ms",
-            coloring_timer.elapsed().as_millis()
+            "Elapsed inner and coloring: {}ms",
+            inner_timer.elapsed().as_millis()
         );
-        Ok(img)
+        Ok((img, iterations))
+    }
+
+    /// Calculate the iteration counts of the `(x, y, w, h)` region of a `width`x`height` image,
+    /// without calculating the rest of the image.
+    pub fn generate_region(
+        &mut self,
+        (x, y, w, h): (u32, u32, u32, u32),
+        width: u32,
+        height: u32,
+        fp: FractalProperties,
+    ) -> Result<Vec<f64>, String> {
+        self.prepare(width, height, fp)?;
+        let kernel = self.kernel.as_ref().unwrap();
+        let buffer = self.buffer.as_ref().unwrap();
+
+        unsafe {
+            kernel
+                .cmd()
+                .global_work_offset((x as usize, y as usize))
+                .global_work_size((w as usize, h as usize))
+                .enq()?;
+        }
+
+        let mut region = Vec::with_capacity(w as usize * h as usize);
+        let mut row = vec![0.0f64; w as usize];
+        for row_y in y..y + h {
+            buffer
+                .read(&mut row)
+                .offset(row_y as usize * width as usize + x as usize)
+                .enq()?;
+            region.extend_from_slice(&row);
+        }
+        Ok(region)
+    }
+
+    /// Make sure the `pro_que` matches the image size and set the kernel arguments.
+    fn prepare(&mut self, width: u32, height: u32, fp: FractalProperties) -> Result<(), String> {
+        let build_timer = Instant::now();
+        if self.pro_que.is_none() {
+            println!("Built OpenCL pro_que");
+            self.build(width, height)?;
+        } else if self.size != (width, height) {
+            // Width and height changed, only the dims and the buffer have to follow
+            println!("Resized OpenCL pro_que");
+            self.resize(width, height)?;
+        }
+
+        // Set opencl kernel args
+        let kernel = self.kernel.as_ref().unwrap();
+        let transform = ViewTransform::new(&fp, width as f64, height as f64);
+        kernel.set_arg(0i32, fp)?;
+        kernel.set_arg(1i32, transform)?;
+        kernel.set_arg(2i32, width)?;
+        kernel.set_arg(3i32, height)?;
+        kernel.set_arg(4i32, self.buffer.as_ref().unwrap())?;
+        println!("Elapsed build: {}ms", build_timer.elapsed().as_millis());
+        Ok(())
+    }
+
+    fn resize(&mut self, width: u32, height: u32) -> Result<(), String> {
+        let pro_que = self.pro_que.as_mut().unwrap();
+        pro_que.set_dims(SpatialDims::Two(width as usize, height as usize));
+        self.buffer = Some(pro_que.create_buffer::<f64>()?);
+        self.size = (width, height);
+        Ok(())
     }
 
     fn build(&mut self, width: u32, height: u32) -> Result<(), String> {
@@ -129,10 +216,14 @@ impl OpenCLRenderer {
             pro_que
                 .kernel_builder("mandelbrot")
                 .arg_named("fp", FractalProperties::default())
+                .arg_named("transform", ViewTransform::default())
+                .arg_named("width", width)
+                .arg_named("height", height)
                 .arg_named("buffer", None::<&Buffer<f64>>)
                 .build()?,
         );
         self.pro_que = Some(pro_que);
+        self.size = (width, height);
         Ok(())
     }
 }
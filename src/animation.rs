@@ -0,0 +1,280 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::algorithms::mandelbrot::Float;
+
+/// NeuQuant sampling factor used to build the GIF palettes, 1 is the slowest and best
+const GIF_QUANTIZATION_SPEED: i32 = 10;
+
+/// Animated formats the video frames can be encoded into
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnimationFormat {
+    /// Every frame quantised to its own 256 colour palette
+    Gif,
+    /// Lossless animated PNG
+    Apng,
+    /// Uncompressed YUV4MPEG2 stream with 4:4:4 chroma, readable by most video encoders
+    Y4m,
+}
+
+impl AnimationFormat {
+    /// Pick the format from the file extension: `.gif`, `.png`/`.apng` or `.y4m`
+    pub fn from_path(path: impl AsRef<Path>) -> Option<Self> {
+        let extension = path.as_ref().extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "gif" => Some(AnimationFormat::Gif),
+            "png" | "apng" => Some(AnimationFormat::Apng),
+            "y4m" => Some(AnimationFormat::Y4m),
+            _ => None,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            AnimationFormat::Gif => "gif",
+            AnimationFormat::Apng => "png",
+            AnimationFormat::Y4m => "y4m",
+        }
+    }
+}
+
+/// Writes an animation to disk one frame at a time, so that only the current frame is held in
+/// memory.
+pub enum AnimationEncoder {
+    Gif {
+        encoder: gif::Encoder<BufWriter<File>>,
+        width: u16,
+        height: u16,
+        fps: Float,
+        /// Frames written so far
+        frames: u32,
+    },
+    Apng {
+        writer: png::Writer<BufWriter<File>>,
+        path: PathBuf,
+        /// Number of frames in the header, fixed up when fewer are written
+        total_frames: u32,
+        frames: u32,
+    },
+    Y4m(BufWriter<File>),
+}
+
+fn gif_error(e: gif::EncodingError) -> io::Error {
+    match e {
+        gif::EncodingError::Io(e) => e,
+        e => io::Error::new(io::ErrorKind::InvalidInput, e),
+    }
+}
+
+impl AnimationEncoder {
+    /// Create the animation file, the format is picked by the extension of `path`. APNG has to
+    /// know the number of frames up front, the other formats ignore `total_frames`.
+    pub fn create(
+        path: impl AsRef<Path>,
+        width: u32,
+        height: u32,
+        fps: Float,
+        total_frames: u32,
+    ) -> io::Result<Self> {
+        let path = path.as_ref();
+        let format = AnimationFormat::from_path(path).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Unsupported animation format: {}", path.display()),
+            )
+        })?;
+        let file = BufWriter::new(File::create(path)?);
+
+        match format {
+            AnimationFormat::Gif => {
+                let too_large = || {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "GIF frames are at most 65535px",
+                    )
+                };
+                let width = u16::try_from(width).map_err(|_| too_large())?;
+                let height = u16::try_from(height).map_err(|_| too_large())?;
+                let mut encoder = gif::Encoder::new(file, width, height, &[]).map_err(gif_error)?;
+                encoder
+                    .set_repeat(gif::Repeat::Infinite)
+                    .map_err(gif_error)?;
+                Ok(AnimationEncoder::Gif {
+                    encoder,
+                    width,
+                    height,
+                    fps,
+                    frames: 0,
+                })
+            }
+            AnimationFormat::Apng => {
+                let mut encoder = png::Encoder::new(file, width, height);
+                encoder.set_color(png::ColorType::Rgb);
+                encoder.set_depth(png::BitDepth::Eight);
+                let total_frames = total_frames.max(1);
+                encoder.set_animated(total_frames, 0)?;
+                let (delay_num, delay_den) = apng_delay(fps);
+                encoder.set_frame_delay(delay_num, delay_den)?;
+                Ok(AnimationEncoder::Apng {
+                    writer: encoder.write_header()?,
+                    path: path.to_path_buf(),
+                    total_frames,
+                    frames: 0,
+                })
+            }
+            AnimationFormat::Y4m => {
+                let mut file = file;
+                let (numerator, denominator) = fps_fraction(fps);
+                writeln!(
+                    file,
+                    "YUV4MPEG2 W{} H{} F{}:{} Ip A1:1 C444",
+                    width, height, numerator, denominator
+                )?;
+                Ok(AnimationEncoder::Y4m(file))
+            }
+        }
+    }
+
+    pub fn add_frame(&mut self, pixels: &[[u8; 3]]) -> io::Result<()> {
+        match self {
+            AnimationEncoder::Gif {
+                encoder,
+                width,
+                height,
+                fps,
+                frames,
+            } => {
+                let mut frame = gif::Frame::from_rgb_speed(
+                    *width,
+                    *height,
+                    &pixels.concat(),
+                    GIF_QUANTIZATION_SPEED,
+                );
+                // Delays are whole hundredths of a second, round the end of every frame instead
+                // of every delay so that the rounding errors don't add up over the animation
+                let end = |frame: u32| (frame as Float * 100.0 / *fps).round() as u64;
+                let delay = end(*frames + 1) - end(*frames);
+                frame.delay = delay.clamp(1, u16::MAX as u64) as u16;
+                *frames += 1;
+                encoder.write_frame(&frame).map_err(gif_error)
+            }
+            AnimationEncoder::Apng { writer, frames, .. } => {
+                writer.write_image_data(&pixels.concat())?;
+                *frames += 1;
+                Ok(())
+            }
+            AnimationEncoder::Y4m(file) => {
+                let yuv: Vec<[u8; 3]> = pixels.iter().map(|p| rgb_to_yuv(*p)).collect();
+                file.write_all(b"FRAME\n")?;
+                for plane in 0..3 {
+                    let data: Vec<u8> = yuv.iter().map(|p| p[plane]).collect();
+                    file.write_all(&data)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Flush the animation to disk. An APNG that got fewer frames than announced, because the
+    /// video was stopped, gets the frame count in its header rewritten, or is deleted if it
+    /// has no frames at all.
+    pub fn finish(self) -> io::Result<()> {
+        match self {
+            AnimationEncoder::Gif { encoder, .. } => encoder.into_inner()?.flush(),
+            AnimationEncoder::Apng {
+                writer,
+                path,
+                total_frames,
+                frames,
+            } => {
+                writer.finish()?;
+                if frames == 0 {
+                    fs::remove_file(&path)?;
+                    println!("Deleted {}, no frames were written", path.display());
+                } else if frames < total_frames {
+                    set_apng_frames(&path, frames)?;
+                    println!(
+                        "Stopped after {} of {} frames, {} only plays those",
+                        frames,
+                        total_frames,
+                        path.display()
+                    );
+                }
+                Ok(())
+            }
+            AnimationEncoder::Y4m(mut file) => file.flush(),
+        }
+    }
+}
+
+/// Frame rate as a reduced `numerator / denominator` fraction, exact to a thousandth of a frame
+fn fps_fraction(fps: Float) -> (u32, u32) {
+    let (numerator, denominator) = ((fps * 1000.0).round() as u32, 1000);
+    let divisor = gcd(numerator, denominator);
+    (numerator / divisor, denominator / divisor)
+}
+
+fn gcd(a: u32, b: u32) -> u32 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+/// APNG frame delay `numerator / denominator` in seconds, the inverse of the frame rate. Rates
+/// that don't fit into 16 bits get the closest delay in 65535ths of a second.
+fn apng_delay(fps: Float) -> (u16, u16) {
+    let (numerator, denominator) = fps_fraction(fps);
+    match (u16::try_from(denominator), u16::try_from(numerator)) {
+        (Ok(delay_num), Ok(delay_den)) => (delay_num, delay_den),
+        _ => {
+            let delay_num = (u16::MAX as Float / fps).round();
+            (delay_num.clamp(1.0, u16::MAX as Float) as u16, u16::MAX)
+        }
+    }
+}
+
+/// Rewrite the number of frames in the `acTL` chunk of an APNG, and the chunk's checksum
+fn set_apng_frames(path: &Path, frames: u32) -> io::Result<()> {
+    let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+    // Skip the signature, the animation control chunk comes before the image data
+    let mut offset = 8;
+    loop {
+        let mut header = [0u8; 8];
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(&mut header)?;
+        let len = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as u64;
+        match &header[4..] {
+            b"acTL" => break,
+            b"IDAT" => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "APNG without animation control chunk",
+                ))
+            }
+            _ => offset += 12 + len,
+        }
+    }
+    // Type, frame count and number of plays, followed by the CRC of all of them
+    let mut chunk = [0u8; 12];
+    file.seek(SeekFrom::Start(offset + 4))?;
+    file.read_exact(&mut chunk)?;
+    chunk[4..8].copy_from_slice(&frames.to_be_bytes());
+    file.seek(SeekFrom::Start(offset + 4))?;
+    file.write_all(&chunk)?;
+    file.write_all(&crc32fast::hash(&chunk).to_be_bytes())?;
+    file.flush()
+}
+
+/// BT.601 limited range conversion, what YUV4MPEG2 readers assume by default
+fn rgb_to_yuv([r, g, b]: [u8; 3]) -> [u8; 3] {
+    let (r, g, b) = (r as Float, g as Float, b as Float);
+    let y = 16.0 + (65.481 * r + 128.553 * g + 24.966 * b) / 255.0;
+    let u = 128.0 + (-37.797 * r - 74.203 * g + 112.0 * b) / 255.0;
+    let v = 128.0 + (112.0 * r - 93.786 * g - 18.214 * b) / 255.0;
+    [y.round() as u8, u.round() as u8, v.round() as u8]
+}
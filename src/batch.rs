@@ -0,0 +1,330 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "opencl")]
+use crate::algorithms::opencl::OpenCLRenderer;
+use crate::{
+    algorithms::{
+        mandelbrot::{AlgorithmType, Float, FractalProperties},
+        naive_cpu,
+    },
+    export, import,
+    location::Location,
+};
+
+/// Image size used when neither the job nor the manifest defaults give one
+const DEFAULT_SIZE: [u32; 2] = [1920, 1080];
+
+/// Size and properties of an image to render, `(width, height, fp)`
+pub type View = (u32, u32, FractalProperties);
+
+/// Renders images one after the other, keeping the OpenCL program built between them.
+#[derive(Default)]
+pub struct Renderer {
+    #[cfg(feature = "opencl")]
+    opencl_renderer: OpenCLRenderer,
+}
+
+impl Renderer {
+    pub fn render(
+        &mut self,
+        algorithm: &AlgorithmType,
+        width: u32,
+        height: u32,
+        fp: FractalProperties,
+    ) -> Result<Vec<[u8; 3]>, String> {
+        match algorithm {
+            AlgorithmType::NaiveCPU => Ok(naive_cpu::generate_image(width, height, fp)),
+            #[cfg(feature = "opencl")]
+            AlgorithmType::OpenCL => self.opencl_renderer.generate_image(width, height, fp),
+        }
+    }
+
+    /// Render the image, handing every finished tile to `on_tile` as
+    /// `(x, y, width, height, pixels, iterations)`. Returns the image and the iteration count of
+    /// every pixel.
+    pub fn render_tiled<F>(
+        &mut self,
+        algorithm: &AlgorithmType,
+        width: u32,
+        height: u32,
+        fp: FractalProperties,
+        on_tile: F,
+    ) -> Result<(Vec<[u8; 3]>, Vec<Float>), String>
+    where
+        F: Fn(u32, u32, u32, u32, &[[u8; 3]], u64) + Sync,
+    {
+        match algorithm {
+            AlgorithmType::NaiveCPU => {
+                Ok(naive_cpu::generate_image_tiled(width, height, fp, on_tile))
+            }
+            #[cfg(feature = "opencl")]
+            AlgorithmType::OpenCL => self
+                .opencl_renderer
+                .generate_image_tiled(width, height, fp, on_tile),
+        }
+    }
+
+    /// Render every view in order
+    pub fn render_views(
+        &mut self,
+        algorithm: &AlgorithmType,
+        views: &[View],
+    ) -> Result<Vec<Vec<[u8; 3]>>, String> {
+        views
+            .iter()
+            .map(|(width, height, fp)| self.render(algorithm, *width, *height, *fp))
+            .collect()
+    }
+
+    /// Render the supersampled iteration count of every pixel, to be coloured later
+    pub fn render_iterations(
+        &mut self,
+        algorithm: &AlgorithmType,
+        width: u32,
+        height: u32,
+        fp: FractalProperties,
+    ) -> Result<Vec<Float>, String> {
+        let on_tile = |_, _, _, _, _: &[[u8; 3]], _| {};
+        Ok(self.render_tiled(algorithm, width, height, fp, on_tile)?.1)
+    }
+}
+
+/// Settings of a batch job, everything left out falls back to the manifest defaults.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct JobSettings {
+    /// Location file to start from, relative to the manifest
+    pub location: Option<String>,
+    pub center: Option<[Float; 2]>,
+    pub zoom: Option<Float>,
+    pub iterations: Option<Float>,
+    pub ss_factor: Option<i32>,
+    pub rotation: Option<Float>,
+    pub color_offset: Option<Float>,
+    pub color_saturation: Option<Float>,
+    pub size: Option<[u32; 2]>,
+    /// `cpu` or `opencl`
+    pub algorithm: Option<String>,
+}
+
+impl JobSettings {
+    /// Fill in every setting missing here from `defaults`
+    fn or(&self, defaults: &JobSettings) -> JobSettings {
+        JobSettings {
+            location: self.location.clone().or_else(|| defaults.location.clone()),
+            center: self.center.or(defaults.center),
+            zoom: self.zoom.or(defaults.zoom),
+            iterations: self.iterations.or(defaults.iterations),
+            ss_factor: self.ss_factor.or(defaults.ss_factor),
+            rotation: self.rotation.or(defaults.rotation),
+            color_offset: self.color_offset.or(defaults.color_offset),
+            color_saturation: self.color_saturation.or(defaults.color_saturation),
+            size: self.size.or(defaults.size),
+            algorithm: self
+                .algorithm
+                .clone()
+                .or_else(|| defaults.algorithm.clone()),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Job {
+    /// Image to write, relative to the manifest. The format is picked by the extension.
+    pub output: String,
+    #[serde(flatten)]
+    pub settings: JobSettings,
+}
+
+/// List of renders, stored as JSON.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Manifest {
+    #[serde(default)]
+    pub defaults: JobSettings,
+    pub jobs: Vec<Job>,
+}
+
+impl Manifest {
+    pub fn read(path: impl AsRef<Path>) -> io::Result<Self> {
+        let data = fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&data)?)
+    }
+}
+
+/// A job with every setting resolved, ready to be rendered.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResolvedJob {
+    pub output: PathBuf,
+    pub width: u32,
+    pub height: u32,
+    pub algorithm: AlgorithmType,
+    pub fp: FractalProperties,
+    pub warnings: Vec<String>,
+}
+
+impl Job {
+    /// Resolve the settings of this job against the manifest `defaults`, with relative paths
+    /// taken from `base_dir`.
+    pub fn resolve(&self, defaults: &JobSettings, base_dir: &Path) -> Result<ResolvedJob, String> {
+        let settings = self.settings.or(defaults);
+        let mut warnings = vec![];
+
+        let mut fp = match &settings.location {
+            Some(location) => {
+                let path = base_dir.join(location);
+                let import = import::open(&path).map_err(|e| format!("{}: {}", location, e))?;
+                warnings.extend(import.warnings);
+                import.location.properties
+            }
+            None => FractalProperties::default(),
+        };
+        if let Some([x, y]) = settings.center {
+            fp.center_x = x;
+            fp.center_y = y;
+        }
+        fp.zoom = settings.zoom.unwrap_or(fp.zoom);
+        fp.max_iter = settings.iterations.unwrap_or(fp.max_iter);
+        fp.ss_factor = settings.ss_factor.unwrap_or(fp.ss_factor);
+        fp.rotation = settings.rotation.unwrap_or(fp.rotation);
+        fp.color_offset = settings.color_offset.unwrap_or(fp.color_offset);
+        fp.color_saturation = settings.color_saturation.unwrap_or(fp.color_saturation);
+        fp.validate()?;
+
+        let [width, height] = settings.size.unwrap_or(DEFAULT_SIZE);
+        if width == 0 || height == 0 {
+            return Err("Image size must not be empty".to_string());
+        }
+        let algorithm = match &settings.algorithm {
+            Some(algorithm) => algorithm.parse()?,
+            #[cfg(not(feature = "opencl"))]
+            None => AlgorithmType::NaiveCPU,
+            #[cfg(feature = "opencl")]
+            None => AlgorithmType::OpenCL,
+        };
+
+        Ok(ResolvedJob {
+            output: base_dir.join(&self.output),
+            width,
+            height,
+            algorithm,
+            fp,
+            warnings,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Rendered,
+    /// The output already existed
+    Skipped,
+    Failed,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct JobReport {
+    pub output: String,
+    pub status: JobStatus,
+    pub millis: u128,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub warnings: Vec<String>,
+}
+
+/// Outcome of a whole batch, written next to the manifest.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct BatchReport {
+    pub rendered: usize,
+    pub skipped: usize,
+    pub failed: usize,
+    pub millis: u128,
+    pub jobs: Vec<JobReport>,
+}
+
+impl BatchReport {
+    pub fn write(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)?)
+    }
+}
+
+/// Render every job of the manifest in order with the same `renderer`. Jobs whose output
+/// already exists are skipped unless `overwrite` is set, failing jobs don't stop the batch.
+pub fn run_batch(
+    manifest: &Manifest,
+    base_dir: &Path,
+    renderer: &mut Renderer,
+    overwrite: bool,
+) -> BatchReport {
+    let batch_start = Instant::now();
+    let mut report = BatchReport::default();
+    for (i, job) in manifest.jobs.iter().enumerate() {
+        let start = Instant::now();
+        let mut job_report = JobReport {
+            output: job.output.clone(),
+            status: JobStatus::Rendered,
+            millis: 0,
+            error: None,
+            warnings: vec![],
+        };
+
+        let result = job
+            .resolve(&manifest.defaults, base_dir)
+            .and_then(|resolved| {
+                job_report.warnings = resolved.warnings.clone();
+                if !overwrite && resolved.output.exists() {
+                    job_report.status = JobStatus::Skipped;
+                    return Ok(());
+                }
+                let img = renderer.render(
+                    &resolved.algorithm,
+                    resolved.width,
+                    resolved.height,
+                    resolved.fp,
+                )?;
+                let location = Location::new(resolved.fp);
+                export::save_image(
+                    &resolved.output,
+                    resolved.width,
+                    resolved.height,
+                    &img,
+                    &location,
+                )
+                .map_err(|e| e.to_string())
+            });
+        if let Err(e) = result {
+            job_report.status = JobStatus::Failed;
+            job_report.error = Some(e);
+        }
+        job_report.millis = start.elapsed().as_millis();
+
+        match job_report.status {
+            JobStatus::Rendered => report.rendered += 1,
+            JobStatus::Skipped => report.skipped += 1,
+            JobStatus::Failed => report.failed += 1,
+        }
+        println!(
+            "[{}/{}] {:?} {} in: {}ms{}",
+            i + 1,
+            manifest.jobs.len(),
+            job_report.status,
+            job_report.output,
+            job_report.millis,
+            job_report
+                .error
+                .as_ref()
+                .map(|e| format!(" ({})", e))
+                .unwrap_or_default()
+        );
+        report.jobs.push(job_report);
+    }
+    report.millis = batch_start.elapsed().as_millis();
+    report
+}
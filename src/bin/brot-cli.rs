@@ -0,0 +1,573 @@
+//! Headless renderer: renders views to image files without opening a window.
+
+use std::{env, path::Path, process, str::FromStr, time::Instant};
+
+use brot_rs::{
+    algorithms::mandelbrot::{AlgorithmType, Float, FractalProperties},
+    batch::{self, Manifest, Renderer},
+    cycle::{self, CycleSettings},
+    distributed::{self, Coordinator},
+    export, import,
+    location::Location,
+    overlay::{self, Overlay},
+    pyramid::{self, PyramidSettings},
+    server::{self, ServerSettings},
+    tiled::{self, TiledSettings},
+    timeline::Timeline,
+    video::{self, VideoJob, VideoSettings},
+};
+
+const USAGE: &str = "Usage: brot-cli [options] -o <output.png|output.bmp>
+       brot-cli --batch <manifest.json> [--report <report.json>] [--overwrite]
+       brot-cli [options] --video <directory> [video options]
+       brot-cli [-a <algorithm>] --resume <directory>
+       brot-cli [options] --cycle <animation> [colour cycle options]
+       brot-cli [options] --pyramid <file.dzi|directory> [--pyramid-tile <px>]
+       brot-cli [options] --serve <port> [--cache <tiles>]
+       brot-cli [-a <algorithm>] --worker <coordinator address>
+
+Options:
+  -l, --location <file>     start from a location file, PNG saved by brot-rs, .kfr or .xpf
+  -c, --center <x>,<y>      center of the view
+  -z, --zoom <zoom>         zoom, 0.5 shows the whole set
+  -i, --iterations <n>      maximum number of iterations
+  -s, --size <w>x<h>        image size in pixels (default 1920x1080)
+      --ss <n>              supersampling factor per axis
+      --rotation <degrees>  counter-clockwise rotation of the view
+  -a, --algorithm <name>    cpu or opencl
+  -o, --output <file>       output image, the format is picked by the extension
+      --tile-size <px>      render the image in tiles of this size and stream it to a .png or
+                            .tiff in bands of rows, for images too large to fit in memory
+  -h, --help                show this help
+
+Batch mode:
+  -b, --batch <file>        render every job of a JSON manifest
+      --report <file>       summary report (default: <manifest>.report.json)
+      --overwrite           render jobs whose output already exists instead of skipping them
+
+Video mode, zooms from the start zoom to the view given by the options above:
+  -v, --video <directory>   render the frames of a zoom video into the directory
+      --fps <fps>           frame rate (default 30)
+      --duration <seconds>  length of the video (default 10)
+      --start-zoom <zoom>   zoom of the first frame (default 0.5)
+      --end-zoom <zoom>     zoom of the last frame (default: --zoom)
+      --frame-pattern <p>   frame file name, {frame} is replaced by the frame number
+                            (default frame_{frame}.png)
+      --animation <file>    also encode the frames into an animated .gif, .png (APNG) or .y4m
+      --no-frames           only write the animation, not every frame as an image
+      --resume <directory>  continue an interrupted video from the job file in its directory,
+                            keeping the frames already written
+      --timeline <file>     render the keys of a JSON timeline instead of a straight zoom,
+                            starting from the view given by the options above
+      --reproject           render a keyframe at every doubling of the zoom and reproject
+                            the frames from them, much faster than rendering every frame
+      --motion-samples <n>  sub-frames blended into every frame for motion blur (default 1)
+      --shutter-angle <deg> part of the time between frames the sub-frames cover, 0-360
+                            (default 180)
+
+Colour cycle options, the view is rendered once and the palette rotates:
+      --cycle <file>        write a looping .gif, .png (APNG) or .y4m animation
+      --cycle-frames <n>    number of frames (default 60)
+      --cycle-fps <fps>     frame rate (default 30)
+      --cycles <n>          times the palette goes around during the animation (default 1)
+
+Tile pyramid options, every level is rendered at its own resolution:
+      --pyramid <path>      write Deep Zoom tiles next to a .dzi file, or XYZ tiles as
+                            <z>/<x>/<y>.png into a directory. The size gives the full
+                            resolution, XYZ tiles cover the square spanned by the zoom.
+      --pyramid-tile <px>   tile size (default 256)
+
+Server mode, renders on request over HTTP on localhost:
+      --serve <port>        answer /render?cx=..&cy=..&zoom=..&w=..&h=.. and
+                            /tiles/{z}/{x}/{y}.png, the tiles are cut from the view given
+                            by the options above like --pyramid XYZ tiles
+      --cache <tiles>       number of tiles kept in memory (default 512)
+
+Distributed rendering, for --tile-size, --video and --resume:
+      --coordinator <addr>  hand the tiles or frames out to the workers connecting to this
+                            address, like 0.0.0.0:7878, instead of rendering them here
+      --worker <addr>       connect to a coordinator and render its jobs with -a
+
+Overlay options, for images and videos:
+      --overlay <template>  draw text onto the image, {zoom}, {iterations}, {center},
+                            {rotation}, {time} and {frame} are filled in
+      --overlay-position <p>  top-left (default), top-right, bottom-left or bottom-right
+      --overlay-size <px>   font size (default 50)
+      --overlay-color <c>   RRGGBB or RRGGBBAA hex colour (default ffffffff)
+      --font <file>         TrueType or OpenType font of the overlay, required with --overlay
+
+The view options -c, -z, -i, --ss and --rotation override the values from --location,
+wherever they are given.";
+
+struct Options {
+    fp: FractalProperties,
+    width: u32,
+    height: u32,
+    algorithm: AlgorithmType,
+    output: String,
+    batch: Option<String>,
+    /// Directory of an interrupted video to continue
+    resume: Option<String>,
+    report: Option<String>,
+    overwrite: bool,
+    /// Set in video mode, the end zoom and size are filled in from the view options
+    video: Option<VideoSettings>,
+    end_zoom: Option<Float>,
+    overlay: Option<Overlay>,
+    /// Set in colour cycle mode, the size is filled in from the view options
+    cycle: Option<CycleSettings>,
+    /// Set in tiled mode, the output and size are filled in from the options
+    tiled: Option<TiledSettings>,
+    /// Set in tile pyramid mode, the size is filled in from the view options
+    pyramid: Option<PyramidSettings>,
+    /// Set in server mode
+    server: Option<ServerSettings>,
+    /// Address workers connect to, instead of rendering locally
+    coordinator: Option<String>,
+    /// Coordinator to render for in worker mode
+    worker: Option<String>,
+}
+
+fn parse<T: FromStr>(name: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("Invalid value for {}: {}", name, value))
+}
+
+/// Split a `<a><separator><b>` argument like `-0.5,0.1` or `1920x1080`
+fn parse_pair<T: FromStr>(name: &str, value: &str, separator: char) -> Result<(T, T), String> {
+    let (a, b) = value
+        .split_once(separator)
+        .ok_or_else(|| format!("Invalid value for {}: {}", name, value))?;
+    Ok((parse(name, a)?, parse(name, b)?))
+}
+
+/// Overlay of the options, adding one if needed
+fn overlay(options: &mut Options) -> &mut Overlay {
+    options.overlay.get_or_insert_with(Overlay::default)
+}
+
+/// Colour cycle settings of the options, switching to colour cycle mode
+fn cycle(options: &mut Options) -> &mut CycleSettings {
+    options.cycle.get_or_insert_with(CycleSettings::default)
+}
+
+/// Tile pyramid settings of the options, switching to tile pyramid mode
+fn pyramid(options: &mut Options) -> &mut PyramidSettings {
+    options.pyramid.get_or_insert_with(PyramidSettings::default)
+}
+
+/// Server settings of the options, switching to server mode
+fn server(options: &mut Options) -> &mut ServerSettings {
+    options.server.get_or_insert_with(ServerSettings::default)
+}
+
+/// Video settings of the options, switching to video mode
+fn video(options: &mut Options) -> &mut VideoSettings {
+    options.video.get_or_insert_with(VideoSettings::default)
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        fp: FractalProperties::default(),
+        width: 1920,
+        height: 1080,
+        #[cfg(not(feature = "opencl"))]
+        algorithm: AlgorithmType::NaiveCPU,
+        #[cfg(feature = "opencl")]
+        algorithm: AlgorithmType::OpenCL,
+        output: String::new(),
+        batch: None,
+        resume: None,
+        report: None,
+        overwrite: false,
+        video: None,
+        end_zoom: None,
+        overlay: None,
+        cycle: None,
+        tiled: None,
+        pyramid: None,
+        server: None,
+        coordinator: None,
+        worker: None,
+    };
+
+    // Applied on top of the location once every argument is read, so that their order
+    // doesn't matter
+    let mut location = None;
+    let mut view_options = vec![];
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "-h" || arg == "--help" {
+            println!("{}", USAGE);
+            process::exit(0);
+        }
+        if arg == "--overwrite" {
+            options.overwrite = true;
+            continue;
+        }
+        if arg == "--no-frames" {
+            video(&mut options).save_frames = false;
+            continue;
+        }
+        if arg == "--reproject" {
+            video(&mut options).reproject = true;
+            continue;
+        }
+        let value = args
+            .next()
+            .ok_or_else(|| format!("Missing value for {}", arg))?;
+        match arg.as_str() {
+            "-l" | "--location" => location = Some(value),
+            "-c" | "--center" | "-z" | "--zoom" | "-i" | "--iterations" | "--ss" | "--rotation" => {
+                view_options.push((arg, value))
+            }
+            "-s" | "--size" => (options.width, options.height) = parse_pair(arg, value, 'x')?,
+            "-a" | "--algorithm" => options.algorithm = value.parse()?,
+            "-o" | "--output" => options.output = value.clone(),
+            "--tile-size" => {
+                options.tiled = Some(TiledSettings {
+                    tile_size: parse(arg, value)?,
+                    ..TiledSettings::default()
+                })
+            }
+            "-b" | "--batch" => options.batch = Some(value.clone()),
+            "--resume" => options.resume = Some(value.clone()),
+            "--report" => options.report = Some(value.clone()),
+            "-v" | "--video" => video(&mut options).output_dir = value.into(),
+            "--fps" => video(&mut options).fps = parse(arg, value)?,
+            "--duration" => video(&mut options).duration = parse(arg, value)?,
+            "--start-zoom" => video(&mut options).start_zoom = parse(arg, value)?,
+            "--end-zoom" => options.end_zoom = Some(parse(arg, value)?),
+            "--frame-pattern" => video(&mut options).file_pattern = value.clone(),
+            "--animation" => video(&mut options).animation = Some(value.into()),
+            "--motion-samples" => video(&mut options).motion_samples = parse(arg, value)?,
+            "--shutter-angle" => video(&mut options).shutter_angle = parse(arg, value)?,
+            "--timeline" => {
+                let timeline = Timeline::read(value).map_err(|e| format!("{}: {}", value, e))?;
+                video(&mut options).timeline = Some(timeline);
+            }
+            "--cycle" => cycle(&mut options).output = value.into(),
+            "--cycle-frames" => cycle(&mut options).frames = parse(arg, value)?,
+            "--cycle-fps" => cycle(&mut options).fps = parse(arg, value)?,
+            "--cycles" => cycle(&mut options).cycles = parse(arg, value)?,
+            "--pyramid" => pyramid(&mut options).output = value.into(),
+            "--pyramid-tile" => pyramid(&mut options).tile_size = parse(arg, value)?,
+            "--serve" => server(&mut options).port = parse(arg, value)?,
+            "--cache" => server(&mut options).cache_size = parse(arg, value)?,
+            "--coordinator" => options.coordinator = Some(value.clone()),
+            "--worker" => options.worker = Some(value.clone()),
+            "--overlay" => overlay(&mut options).template = value.replace("\\n", "\n"),
+            "--overlay-position" => overlay(&mut options).anchor = value.parse()?,
+            "--overlay-size" => overlay(&mut options).size = parse(arg, value)?,
+            "--overlay-color" => overlay(&mut options).color = overlay::parse_color(value)?,
+            "--font" => overlay(&mut options).font = Some(value.into()),
+            _ => return Err(format!("Unknown option: {}", arg)),
+        }
+    }
+
+    if let Some(path) = location {
+        let import = import::open(path).map_err(|e| format!("{}: {}", path, e))?;
+        for warning in &import.warnings {
+            eprintln!("{}: {}", path, warning);
+        }
+        options.fp = import.location.properties;
+    }
+    let fp = &mut options.fp;
+    for (arg, value) in view_options {
+        match arg.as_str() {
+            "-c" | "--center" => (fp.center_x, fp.center_y) = parse_pair(arg, value, ',')?,
+            "-z" | "--zoom" => fp.zoom = parse(arg, value)?,
+            "-i" | "--iterations" => fp.max_iter = parse(arg, value)?,
+            "--ss" => fp.ss_factor = parse(arg, value)?,
+            "--rotation" => fp.rotation = parse(arg, value)?,
+            _ => unreachable!("Not a view option: {}", arg),
+        }
+    }
+
+    if let Some(overlay) = &options.overlay {
+        if overlay.font.is_none() {
+            return Err("The overlay needs a --font".to_string());
+        }
+    }
+    if let Some(video) = &mut options.video {
+        video.overlay = options.overlay.clone();
+        video.end_zoom = options.end_zoom.unwrap_or(options.fp.zoom);
+        video.width = options.width;
+        video.height = options.height;
+        video.validate()?;
+    }
+    if let Some(cycle) = &mut options.cycle {
+        cycle.width = options.width;
+        cycle.height = options.height;
+        cycle.validate()?;
+    }
+    if let Some(pyramid) = &mut options.pyramid {
+        pyramid.width = options.width;
+        pyramid.height = options.height;
+        pyramid.validate()?;
+    }
+    if options.output.is_empty()
+        && options.batch.is_none()
+        && options.resume.is_none()
+        && options.video.is_none()
+        && options.cycle.is_none()
+        && options.pyramid.is_none()
+        && options.server.is_none()
+        && options.worker.is_none()
+    {
+        return Err("No output file given".to_string());
+    }
+    if let Some(tiled) = &mut options.tiled {
+        if options.overlay.is_some() {
+            return Err("The overlay can't be drawn onto tiled images".to_string());
+        }
+        tiled.output = options.output.clone().into();
+        tiled.width = options.width;
+        tiled.height = options.height;
+        tiled.validate()?;
+    }
+    if options.coordinator.is_some()
+        && options.tiled.is_none()
+        && options.video.is_none()
+        && options.resume.is_none()
+    {
+        return Err("--coordinator needs --tile-size, --video or --resume".to_string());
+    }
+    if options.width == 0 || options.height == 0 {
+        return Err("Image size must not be empty".to_string());
+    }
+    options.fp.validate()?;
+    Ok(options)
+}
+
+/// Render every job of the manifest and write the summary report, returns whether every job
+/// succeeded.
+fn run_batch(options: &Options, manifest_path: &str) -> bool {
+    let manifest = match Manifest::read(manifest_path) {
+        Ok(manifest) => manifest,
+        Err(e) => {
+            eprintln!("Failed reading manifest {}: {}", manifest_path, e);
+            process::exit(2);
+        }
+    };
+    let base_dir = Path::new(manifest_path).parent().unwrap_or(Path::new(""));
+
+    let mut renderer = Renderer::default();
+    let report = batch::run_batch(&manifest, base_dir, &mut renderer, options.overwrite);
+    println!(
+        "Batch finished in: {}ms, {} rendered, {} skipped, {} failed",
+        report.millis, report.rendered, report.skipped, report.failed
+    );
+
+    let report_path = options
+        .report
+        .clone()
+        .unwrap_or_else(|| format!("{}.report.json", manifest_path));
+    if let Err(e) = report.write(&report_path) {
+        eprintln!("Failed writing report {}: {}", report_path, e);
+    }
+    report.failed == 0
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let options = match parse_args(&args) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            process::exit(2);
+        }
+    };
+
+    if let Some(address) = &options.worker {
+        let result = distributed::run_worker(
+            address.as_str(),
+            &options.algorithm,
+            &mut Renderer::default(),
+        );
+        if let Err(e) = result {
+            eprintln!("Worker failed: {}", e);
+            process::exit(1);
+        }
+        return;
+    }
+
+    if let Some(address) = &options.coordinator {
+        let mut coordinator =
+            match Coordinator::bind(address.as_str(), distributed::DEFAULT_JOB_TIMEOUT) {
+                Ok(coordinator) => coordinator,
+                Err(e) => {
+                    eprintln!("Failed listening on {}: {}", address, e);
+                    process::exit(1);
+                }
+            };
+        let result = if let Some(dir) = &options.resume {
+            distributed::resume_video(VideoJob::path(dir), &mut coordinator, |frame| {
+                println!("Frame {}", frame + 1)
+            })
+        } else if let Some(settings) = &options.video {
+            let total_frames = settings.total_frames();
+            distributed::export_video(settings, options.fp, &mut coordinator, |frame| {
+                println!("Frame {}/{}", frame + 1, total_frames)
+            })
+        } else {
+            let settings = options.tiled.as_ref().unwrap();
+            let bands = settings.bands();
+            distributed::render_tiled(settings, options.fp, &mut coordinator, |band| {
+                println!("Band {}/{}", band + 1, bands)
+            })
+        };
+        if let Err(e) = result {
+            eprintln!("Distributed render failed: {}", e);
+            process::exit(1);
+        }
+        return;
+    }
+
+    if let Some(dir) = &options.resume {
+        let result = video::resume_video(
+            VideoJob::path(dir),
+            &options.algorithm,
+            &mut Renderer::default(),
+            |frame| println!("Frame {}", frame + 1),
+        );
+        if let Err(e) = result {
+            eprintln!("Video failed: {}", e);
+            process::exit(1);
+        }
+        return;
+    }
+
+    if let Some(manifest_path) = &options.batch {
+        let success = run_batch(&options, manifest_path);
+        process::exit(if success { 0 } else { 1 });
+    }
+
+    if let Some(settings) = &options.video {
+        let total_frames = settings.total_frames();
+        let result = video::export_video(
+            settings,
+            options.fp,
+            &options.algorithm,
+            &mut Renderer::default(),
+            |frame| println!("Frame {}/{}", frame + 1, total_frames),
+        );
+        if let Err(e) = result {
+            eprintln!("Video failed: {}", e);
+            process::exit(1);
+        }
+        return;
+    }
+
+    if let Some(settings) = &options.cycle {
+        let result = cycle::export_cycle(
+            settings,
+            options.fp,
+            &options.algorithm,
+            &mut Renderer::default(),
+            |frame| println!("Frame {}/{}", frame + 1, settings.frames),
+        );
+        if let Err(e) = result {
+            eprintln!("Colour cycle failed: {}", e);
+            process::exit(1);
+        }
+        return;
+    }
+
+    if let Some(settings) = &options.server {
+        let result = server::bind(settings.port).and_then(|listener| {
+            server::serve(
+                listener,
+                settings,
+                options.fp,
+                &options.algorithm,
+                &mut Renderer::default(),
+            )
+        });
+        if let Err(e) = result {
+            eprintln!("Server failed: {}", e);
+            process::exit(1);
+        }
+        return;
+    }
+
+    if let Some(settings) = &options.pyramid {
+        let result = pyramid::export_pyramid(
+            settings,
+            options.fp,
+            &options.algorithm,
+            &mut Renderer::default(),
+            |done, total| println!("Tile {}/{}", done, total),
+        );
+        if let Err(e) = result {
+            eprintln!("Tile pyramid failed: {}", e);
+            process::exit(1);
+        }
+        return;
+    }
+
+    if let Some(settings) = &options.tiled {
+        let bands = settings.bands();
+        let result = tiled::render_tiled(
+            settings,
+            options.fp,
+            &options.algorithm,
+            &mut Renderer::default(),
+            |band| println!("Band {}/{}", band + 1, bands),
+        );
+        if let Err(e) = result {
+            eprintln!("Tiled render failed: {}", e);
+            process::exit(1);
+        }
+        println!("Saved image to {}", options.output);
+        return;
+    }
+
+    let start = Instant::now();
+    let mut img = match Renderer::default().render(
+        &options.algorithm,
+        options.width,
+        options.height,
+        options.fp,
+    ) {
+        Ok(img) => img,
+        Err(e) => {
+            eprintln!("Rendering failed: {}", e);
+            process::exit(1);
+        }
+    };
+    println!(
+        "Rendered {}x{} with {:?} in: {}ms",
+        options.width,
+        options.height,
+        options.algorithm,
+        start.elapsed().as_millis()
+    );
+
+    if let Some(overlay) = &options.overlay {
+        match overlay.load_font(None) {
+            Ok(font) => {
+                let text = overlay.text(&options.fp, None, None);
+                overlay.draw(&font, &text, options.width, options.height, &mut img);
+            }
+            Err(e) => {
+                eprintln!("Failed loading the overlay font: {}", e);
+                process::exit(1);
+            }
+        }
+    }
+
+    let location = Location::new(options.fp);
+    if let Err(e) = export::save_image(
+        &options.output,
+        options.width,
+        options.height,
+        &img,
+        &location,
+    ) {
+        eprintln!("Failed saving {}: {}", options.output, e);
+        process::exit(1);
+    }
+    println!("Saved image to {}", options.output);
+}
@@ -0,0 +1,120 @@
+use std::{fs, path::PathBuf, time::Instant};
+
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    algorithms::{
+        coloring::calculate_pixel_color_cycled,
+        mandelbrot::{AlgorithmType, Float, FractalProperties},
+    },
+    animation::{AnimationEncoder, AnimationFormat},
+    batch::Renderer,
+};
+
+/// Colour cycling animation: the view is rendered once and every frame rotates the hue of the
+/// palette a bit further.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct CycleSettings {
+    /// Animation to write, the format is picked by the extension
+    pub output: PathBuf,
+    pub frames: u32,
+    pub fps: Float,
+    /// Times the palette goes all the way around during the animation
+    pub cycles: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Default for CycleSettings {
+    fn default() -> Self {
+        Self {
+            output: PathBuf::from("cycle.gif"),
+            frames: 60,
+            fps: 30.0,
+            cycles: 1,
+            width: 640,
+            height: 480,
+        }
+    }
+}
+
+impl CycleSettings {
+    /// Hue rotation of the given frame in degrees. The frame after the last one would be the
+    /// first one again, so the animation loops without a stutter.
+    pub fn phase(&self, frame: u32) -> Float {
+        360.0 * self.cycles as Float * frame as Float / self.frames as Float
+    }
+
+    /// Colour the iteration counts for the given frame
+    pub fn frame_pixels(
+        &self,
+        iterations: &[Float],
+        fp: FractalProperties,
+        frame: u32,
+    ) -> Vec<[u8; 3]> {
+        let phase = self.phase(frame);
+        iterations
+            .par_iter()
+            .map(|n| calculate_pixel_color_cycled(fp, *n, phase))
+            .collect()
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if !(self.fps > 0.0 && self.frames > 0 && self.cycles > 0) {
+            return Err("Frames, cycles and frame rate must be positive".to_string());
+        }
+        if self.width == 0 || self.height == 0 {
+            return Err("Image size must not be empty".to_string());
+        }
+        if AnimationFormat::from_path(&self.output).is_none() {
+            return Err(format!(
+                "Unsupported animation format {}, use .gif, .png or .y4m",
+                self.output.display()
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Render the view once and write the colour cycling animation. `on_frame` is called after
+/// every written frame.
+pub fn export_cycle<F>(
+    settings: &CycleSettings,
+    fp: FractalProperties,
+    algorithm: &AlgorithmType,
+    renderer: &mut Renderer,
+    on_frame: F,
+) -> Result<(), String>
+where
+    F: Fn(u32),
+{
+    settings.validate()?;
+    let start = Instant::now();
+    let iterations = renderer.render_iterations(algorithm, settings.width, settings.height, fp)?;
+
+    let error = |e| format!("Failed writing {}: {}", settings.output.display(), e);
+    if let Some(dir) = settings.output.parent() {
+        fs::create_dir_all(dir).map_err(error)?;
+    }
+    let mut encoder = AnimationEncoder::create(
+        &settings.output,
+        settings.width,
+        settings.height,
+        settings.fps,
+        settings.frames,
+    )
+    .map_err(error)?;
+    for frame in 0..settings.frames {
+        let pixels = settings.frame_pixels(&iterations, fp, frame);
+        encoder.add_frame(&pixels).map_err(error)?;
+        on_frame(frame);
+    }
+    encoder.finish().map_err(error)?;
+    println!(
+        "Finished the colour cycle in: {}ms",
+        start.elapsed().as_millis()
+    );
+    Ok(())
+}
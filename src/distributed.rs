@@ -0,0 +1,427 @@
+use std::{
+    io::{self, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use crossbeam_channel::{Receiver, RecvTimeoutError, Sender};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    algorithms::mandelbrot::{AlgorithmType, FractalProperties},
+    batch::{Renderer, View},
+    tiled::{self, TiledSettings},
+    video::{self, VideoSettings, VideoWriter},
+};
+
+/// Sent by workers when connecting, coordinators refuse workers speaking another version
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Workers taking longer than this for a job are considered dead, renders fail once no worker
+/// was connected for this long
+pub const DEFAULT_JOB_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// Video frames handed out to the workers at once
+const FRAMES_PER_BATCH: u32 = 8;
+
+/// How often idle threads check whether the coordinator shut down
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Largest job message workers accept, jobs are a few hundred bytes of JSON
+const MAX_JOB_SIZE: usize = 64 * 1024;
+
+/// Longest error message of a failed job, longer ones are cut off by the worker
+const MAX_ERROR_SIZE: usize = 1024;
+
+/// Most pixels of a job workers render, 192 MiB of RGB
+pub const MAX_JOB_PIXELS: u64 = 8192 * 8192;
+
+// Every message is a tag byte, the payload length as big endian u32 and the payload
+const HELLO: u8 = 0;
+const JOB: u8 = 1;
+const PIXELS: u8 = 2;
+const FAILED: u8 = 3;
+const SHUTDOWN: u8 = 4;
+
+/// Image rendered by a worker. Sent as JSON, the result comes back as the id followed by the
+/// raw RGB pixels.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct RenderJob {
+    pub id: u64,
+    pub width: u32,
+    pub height: u32,
+    pub fp: FractalProperties,
+}
+
+impl RenderJob {
+    /// Check the job before rendering it, workers don't trust the coordinator's views
+    pub fn validate(&self) -> Result<(), String> {
+        let pixels = self.width as u64 * self.height as u64;
+        if pixels == 0 || pixels > MAX_JOB_PIXELS {
+            return Err(format!(
+                "Job size must be between 1 and {} pixels, not {}x{}",
+                MAX_JOB_PIXELS, self.width, self.height
+            ));
+        }
+        self.fp.validate()
+    }
+}
+
+fn write_message(stream: &mut impl Write, tag: u8, payload: &[u8]) -> io::Result<()> {
+    let len = u32::try_from(payload.len()).map_err(|_| invalid_data("Message too large"))?;
+    stream.write_all(&[tag])?;
+    stream.write_all(&len.to_be_bytes())?;
+    stream.write_all(payload)?;
+    stream.flush()
+}
+
+/// Read a message of at most `max_len` bytes. The limit is checked before anything is
+/// allocated, so that peers can't make us reserve memory by announcing huge messages.
+fn read_message(stream: &mut impl Read, max_len: usize) -> io::Result<(u8, Vec<u8>)> {
+    let mut header = [0u8; 5];
+    stream.read_exact(&mut header)?;
+    let len = u32::from_be_bytes([header[1], header[2], header[3], header[4]]) as usize;
+    if len > max_len {
+        return Err(invalid_data("Message too large"));
+    }
+    let mut payload = vec![0u8; len];
+    stream.read_exact(&mut payload)?;
+    Ok((header[0], payload))
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Split a result payload into the job id and the rest
+fn split_id(payload: &[u8]) -> io::Result<(u64, &[u8])> {
+    if payload.len() < 8 {
+        return Err(invalid_data("Result without a job id"));
+    }
+    let (id, rest) = payload.split_at(8);
+    Ok((u64::from_be_bytes(id.try_into().unwrap()), rest))
+}
+
+/// Connect to a coordinator and render its jobs with the local `algorithm` until it shuts
+/// down or disconnects.
+pub fn run_worker(
+    address: impl ToSocketAddrs,
+    algorithm: &AlgorithmType,
+    renderer: &mut Renderer,
+) -> io::Result<()> {
+    let mut stream = TcpStream::connect(address)?;
+    write_message(&mut stream, HELLO, &PROTOCOL_VERSION.to_be_bytes())?;
+    println!("Connected to coordinator {}", stream.peer_addr()?);
+    loop {
+        let (tag, payload) = match read_message(&mut stream, MAX_JOB_SIZE) {
+            Ok(message) => message,
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        match tag {
+            JOB => {
+                let start = Instant::now();
+                let job: RenderJob = serde_json::from_slice(&payload)?;
+                let mut reply = job.id.to_be_bytes().to_vec();
+                let result = job
+                    .validate()
+                    .and_then(|()| renderer.render(algorithm, job.width, job.height, job.fp));
+                match result {
+                    Ok(pixels) => {
+                        reply.extend(pixels.iter().flatten());
+                        write_message(&mut stream, PIXELS, &reply)?;
+                    }
+                    Err(e) => {
+                        reply.extend(e.bytes().take(MAX_ERROR_SIZE));
+                        write_message(&mut stream, FAILED, &reply)?;
+                    }
+                }
+                println!(
+                    "Rendered job {} ({}x{}) in: {}ms",
+                    job.id,
+                    job.width,
+                    job.height,
+                    start.elapsed().as_millis()
+                );
+            }
+            SHUTDOWN => return Ok(()),
+            _ => return Err(invalid_data("Unknown message from the coordinator")),
+        }
+    }
+}
+
+type JobResult = (u64, Result<Vec<[u8; 3]>, String>);
+
+/// Hands out render jobs to the workers connected to it. Every worker gets one job at a time,
+/// the jobs of workers that disconnect or time out go back to the queue for the others.
+pub struct Coordinator {
+    address: SocketAddr,
+    jobs: Sender<RenderJob>,
+    queue: Receiver<RenderJob>,
+    results: Receiver<JobResult>,
+    workers: Arc<AtomicUsize>,
+    shutdown: Arc<AtomicBool>,
+    job_timeout: Duration,
+    next_id: u64,
+}
+
+impl Coordinator {
+    /// Listen for workers on `address`, port 0 picks a free one
+    pub fn bind(address: impl ToSocketAddrs, job_timeout: Duration) -> io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        listener.set_nonblocking(true)?;
+        let (jobs, queue) = crossbeam_channel::unbounded();
+        let (result_sender, results) = crossbeam_channel::unbounded();
+        let coordinator = Self {
+            address: listener.local_addr()?,
+            jobs: jobs.clone(),
+            queue: queue.clone(),
+            results,
+            workers: Arc::new(AtomicUsize::new(0)),
+            shutdown: Arc::new(AtomicBool::new(false)),
+            job_timeout,
+            next_id: 0,
+        };
+
+        let (workers, shutdown) = (coordinator.workers.clone(), coordinator.shutdown.clone());
+        thread::spawn(move || {
+            while !shutdown.load(Ordering::Relaxed) {
+                match listener.accept() {
+                    Ok((stream, _)) => {
+                        let worker = Worker {
+                            stream,
+                            jobs: jobs.clone(),
+                            queue: queue.clone(),
+                            results: result_sender.clone(),
+                            workers: workers.clone(),
+                            shutdown: shutdown.clone(),
+                            job_timeout,
+                        };
+                        thread::spawn(move || worker.run());
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => thread::sleep(POLL_INTERVAL),
+                    Err(e) => {
+                        eprintln!("Failed accepting a worker: {}", e);
+                        thread::sleep(POLL_INTERVAL);
+                    }
+                }
+            }
+        });
+        Ok(coordinator)
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// Number of workers currently connected
+    pub fn workers(&self) -> usize {
+        self.workers.load(Ordering::Relaxed)
+    }
+
+    /// Render the views on the workers and return the images in order. Waits for workers to
+    /// connect if there are none, fails once no worker was connected for the job timeout.
+    pub fn render(&mut self, views: &[View]) -> Result<Vec<Vec<[u8; 3]>>, String> {
+        if self.workers() == 0 {
+            println!("Waiting for workers to connect to {}", self.address);
+        }
+        let first_id = self.next_id;
+        self.next_id += views.len() as u64;
+        for (i, (width, height, fp)) in views.iter().enumerate() {
+            let job = RenderJob {
+                id: first_id + i as u64,
+                width: *width,
+                height: *height,
+                fp: *fp,
+            };
+            self.jobs.send(job).unwrap();
+        }
+
+        let mut images = vec![None; views.len()];
+        let mut remaining = views.len();
+        while remaining > 0 {
+            let (id, result) = match self.results.recv_timeout(self.job_timeout) {
+                Ok(result) => result,
+                // Hung workers are dropped after the job timeout, their jobs are requeued
+                Err(RecvTimeoutError::Timeout) if self.workers() > 0 => continue,
+                Err(_) => {
+                    while self.queue.try_recv().is_ok() {}
+                    return Err(format!(
+                        "No worker connected to {} within {}s",
+                        self.address,
+                        self.job_timeout.as_secs_f64()
+                    ));
+                }
+            };
+            // Results of an earlier, failed render
+            if id < first_id {
+                continue;
+            }
+            let image = &mut images[(id - first_id) as usize];
+            match result {
+                Ok(pixels) if image.is_none() => {
+                    *image = Some(pixels);
+                    remaining -= 1;
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    // Nobody is waiting for the rest of the jobs anymore
+                    while self.queue.try_recv().is_ok() {}
+                    return Err(format!("Job {} failed: {}", id, e));
+                }
+            }
+        }
+        Ok(images.into_iter().map(Option::unwrap).collect())
+    }
+}
+
+impl Drop for Coordinator {
+    fn drop(&mut self) {
+        self.shutdown.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Connection to a single worker, feeding it jobs from the queue
+struct Worker {
+    stream: TcpStream,
+    jobs: Sender<RenderJob>,
+    queue: Receiver<RenderJob>,
+    results: Sender<JobResult>,
+    workers: Arc<AtomicUsize>,
+    shutdown: Arc<AtomicBool>,
+    job_timeout: Duration,
+}
+
+impl Worker {
+    fn run(mut self) {
+        let address = self
+            .stream
+            .peer_addr()
+            .map(|a| a.to_string())
+            .unwrap_or_default();
+        if let Err(e) = self.handshake() {
+            eprintln!("Refused worker {}: {}", address, e);
+            return;
+        }
+        self.workers.fetch_add(1, Ordering::Relaxed);
+        println!("Worker {} connected", address);
+
+        loop {
+            let job = match self.queue.recv_timeout(POLL_INTERVAL) {
+                Ok(job) => job,
+                Err(RecvTimeoutError::Timeout) if !self.shutdown.load(Ordering::Relaxed) => {
+                    continue
+                }
+                Err(_) => {
+                    let _ = write_message(&mut self.stream, SHUTDOWN, &[]);
+                    break;
+                }
+            };
+            match self.render(&job) {
+                Ok(result) => self.results.send((job.id, result)).unwrap(),
+                Err(e) => {
+                    println!(
+                        "Worker {} failed ({}), reassigning job {}",
+                        address, e, job.id
+                    );
+                    let _ = self.jobs.send(job);
+                    break;
+                }
+            }
+        }
+        self.workers.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    fn handshake(&mut self) -> io::Result<()> {
+        self.stream.set_nonblocking(false)?;
+        self.stream.set_read_timeout(Some(self.job_timeout))?;
+        let (tag, payload) = read_message(&mut self.stream, PROTOCOL_VERSION.to_be_bytes().len())?;
+        if tag != HELLO || payload != PROTOCOL_VERSION.to_be_bytes() {
+            return Err(invalid_data("Not a worker of this protocol version"));
+        }
+        Ok(())
+    }
+
+    /// Have the worker render the job. Errors mean the worker is gone, a render failing on
+    /// the worker is returned as the result.
+    fn render(&mut self, job: &RenderJob) -> io::Result<Result<Vec<[u8; 3]>, String>> {
+        write_message(&mut self.stream, JOB, &serde_json::to_vec(job)?)?;
+        let pixels = job.width as usize * job.height as usize * 3;
+        let (tag, payload) = read_message(&mut self.stream, 8 + pixels.max(MAX_ERROR_SIZE))?;
+        let (id, data) = split_id(&payload)?;
+        if id != job.id {
+            return Err(invalid_data("Result for the wrong job"));
+        }
+        match tag {
+            PIXELS if data.len() == job.width as usize * job.height as usize * 3 => Ok(Ok(data
+                .chunks_exact(3)
+                .map(|p| [p[0], p[1], p[2]])
+                .collect())),
+            FAILED => Ok(Err(String::from_utf8_lossy(data).into_owned())),
+            _ => Err(invalid_data("Unexpected result")),
+        }
+    }
+}
+
+/// Render a tiled image on the workers, see [`tiled::render_tiled`]. The tiles of a band are
+/// rendered in parallel, one band after the other.
+pub fn render_tiled<F>(
+    settings: &TiledSettings,
+    fp: FractalProperties,
+    coordinator: &mut Coordinator,
+    on_band: F,
+) -> Result<(), String>
+where
+    F: Fn(u32),
+{
+    tiled::write_tiled(settings, fp, |y| {
+        let tiles = coordinator.render(&tiled::band_views(settings, fp, y))?;
+        on_band(y / settings.tile_size);
+        Ok(tiled::assemble_band(settings, y, &tiles))
+    })
+}
+
+/// Render a video on the workers, see [`video::export_video`]
+pub fn export_video<F>(
+    settings: &VideoSettings,
+    fp: FractalProperties,
+    coordinator: &mut Coordinator,
+    on_frame: F,
+) -> Result<(), String>
+where
+    F: Fn(u32),
+{
+    settings.validate()?;
+    let writer = VideoWriter::new(settings.clone(), fp, None)
+        .map_err(|e| format!("Failed creating the video: {}", e))?;
+    video::render_video(
+        writer,
+        FRAMES_PER_BATCH,
+        |views| coordinator.render(views),
+        on_frame,
+    )
+}
+
+/// Continue an interrupted video on the workers, see [`video::resume_video`]
+pub fn resume_video<F>(
+    job_path: impl AsRef<Path>,
+    coordinator: &mut Coordinator,
+    on_frame: F,
+) -> Result<(), String>
+where
+    F: Fn(u32),
+{
+    let writer = video::resume_writer(job_path)?;
+    video::render_video(
+        writer,
+        FRAMES_PER_BATCH,
+        |views| coordinator.render(views),
+        on_frame,
+    )
+}
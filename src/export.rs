@@ -0,0 +1,121 @@
+use std::{
+    ffi::OsString,
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use image::{ImageBuffer, ImageFormat, RgbImage};
+
+use crate::location::Location;
+
+/// Keyword of the iTXt chunk holding the location JSON
+pub const LOCATION_KEYWORD: &str = "brot_rs:location";
+
+/// Write an RGB image as PNG, embedding the location it was rendered from.
+///
+/// The complete location is stored as JSON in an iTXt chunk, so the exact view can be restored
+/// with [`read_png_location`]. A readable summary is added as standard tEXt chunks for other
+/// image viewers.
+pub fn save_png(
+    path: impl AsRef<Path>,
+    width: u32,
+    height: u32,
+    pixels: &[[u8; 3]],
+    location: &Location,
+) -> io::Result<()> {
+    let file = BufWriter::new(File::create(path)?);
+    let mut writer = png_encoder(file, width, height, location)?.write_header()?;
+    writer.write_image_data(pixels.concat().as_slice())?;
+    writer.finish()?;
+    Ok(())
+}
+
+/// PNG encoder for an RGB image with the location chunks of [`save_png`] added, ready for the
+/// header to be written.
+pub fn png_encoder<W: Write>(
+    w: W,
+    width: u32,
+    height: u32,
+    location: &Location,
+) -> io::Result<png::Encoder<'static, W>> {
+    let mut encoder = png::Encoder::new(w, width, height);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let fp = &location.properties;
+    encoder.add_text_chunk("Software".to_string(), "brot-rs".to_string())?;
+    encoder.add_text_chunk(
+        "Title".to_string(),
+        format!("{:?} at {}, {}", location.formula, fp.center_x, fp.center_y),
+    )?;
+    encoder.add_text_chunk(
+        "Description".to_string(),
+        format!(
+            "zoom: {}, max iter: {}, ss factor: {}, rotation: {}",
+            fp.zoom, fp.max_iter, fp.ss_factor, fp.rotation
+        ),
+    )?;
+    encoder.add_itxt_chunk(LOCATION_KEYWORD.to_string(), location.to_json())?;
+    Ok(encoder)
+}
+
+/// Write an RGB image in the format picked by the extension of `path`. PNGs get the location
+/// embedded, other formats are written through `image` without metadata.
+///
+/// The image is written next to `path` first and only renamed once it's complete, so an
+/// interrupted render never leaves a broken image that would be skipped when resuming.
+pub fn save_image(
+    path: impl AsRef<Path>,
+    width: u32,
+    height: u32,
+    pixels: &[[u8; 3]],
+    location: &Location,
+) -> io::Result<()> {
+    let path = path.as_ref();
+    let partial = partial_path(path);
+    let is_png = path
+        .extension()
+        .map_or(false, |ext| ext.eq_ignore_ascii_case("png"));
+    if is_png {
+        save_png(&partial, width, height, pixels, location)?;
+    } else {
+        let format = ImageFormat::from_path(path)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let img = to_image_buffer(width, height, pixels)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Image size mismatch"))?;
+        img.save_with_format(&partial, format)
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+    }
+    fs::rename(partial, path)
+}
+
+/// Copy rendered pixels into an `image` buffer, `None` if they don't match the size
+pub fn to_image_buffer(width: u32, height: u32, pixels: &[[u8; 3]]) -> Option<RgbImage> {
+    ImageBuffer::from_raw(width, height, pixels.concat())
+}
+
+/// `path` with `.part` appended, where files are written before they're complete
+pub fn partial_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().map(OsString::from).unwrap_or_default();
+    name.push(".part");
+    path.with_file_name(name)
+}
+
+/// Read the location embedded by [`save_png`] back from a PNG file.
+pub fn read_png_location(path: impl AsRef<Path>) -> io::Result<Location> {
+    let decoder = png::Decoder::new(BufReader::new(File::open(path)?));
+    let reader = decoder.read_info()?;
+    let chunk = reader
+        .info()
+        .utf8_text
+        .iter()
+        .find(|chunk| chunk.keyword == LOCATION_KEYWORD)
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                "PNG doesn't contain a brot-rs location",
+            )
+        })?;
+    Location::parse(&chunk.get_text()?)
+}
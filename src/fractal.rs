@@ -0,0 +1,390 @@
+use std::{
+    future::Future,
+    path::Path,
+    pin::Pin,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    task::{Context, Poll, Waker},
+    thread::{self, JoinHandle},
+};
+
+use crossbeam_channel::{unbounded, Receiver};
+use image::RgbImage;
+
+use crate::{
+    algorithms::mandelbrot::{AlgorithmType, Float, FractalProperties},
+    batch::Renderer,
+    export,
+    location::Location,
+};
+
+/// Where images are rendered
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    /// OpenCL if it's compiled in and works on this machine, the CPU otherwise
+    Auto,
+    Cpu,
+    #[cfg(feature = "opencl")]
+    OpenCL,
+}
+
+impl Default for Backend {
+    fn default() -> Self {
+        Backend::Auto
+    }
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "auto" => Ok(Backend::Auto),
+            "cpu" | "naivecpu" => Ok(Backend::Cpu),
+            #[cfg(feature = "opencl")]
+            "opencl" => Ok(Backend::OpenCL),
+            _ => Err(format!("Unknown backend: {}", s)),
+        }
+    }
+}
+
+impl From<AlgorithmType> for Backend {
+    fn from(algorithm: AlgorithmType) -> Self {
+        match algorithm {
+            AlgorithmType::NaiveCPU => Backend::Cpu,
+            #[cfg(feature = "opencl")]
+            AlgorithmType::OpenCL => Backend::OpenCL,
+        }
+    }
+}
+
+/// Finished region of an image that is still being rendered
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tile {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<[u8; 3]>,
+}
+
+/// Sent by a [`RenderTask`] while it renders
+#[derive(Debug, Clone, PartialEq)]
+pub enum RenderEvent {
+    Tile(Tile),
+    Progress {
+        completed_pixels: u64,
+        total_pixels: u64,
+    },
+}
+
+/// Builds a [`FractalRenderer`], starting from the whole set at 1920x1080
+#[derive(Debug, Clone, PartialEq)]
+pub struct RendererBuilder {
+    backend: Backend,
+    width: u32,
+    height: u32,
+    fp: FractalProperties,
+}
+
+impl Default for RendererBuilder {
+    fn default() -> Self {
+        Self {
+            backend: Backend::default(),
+            width: 1920,
+            height: 1080,
+            fp: FractalProperties::default(),
+        }
+    }
+}
+
+impl RendererBuilder {
+    pub fn backend(mut self, backend: Backend) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    /// Image size in pixels
+    pub fn size(mut self, width: u32, height: u32) -> Self {
+        self.width = width;
+        self.height = height;
+        self
+    }
+
+    /// Replace the whole view, the other setters change single properties of it
+    pub fn properties(mut self, fp: FractalProperties) -> Self {
+        self.fp = fp;
+        self
+    }
+
+    /// View of a location, like one read with [`crate::import::open`]
+    pub fn location(self, location: &Location) -> Self {
+        self.properties(location.properties)
+    }
+
+    pub fn center(mut self, x: Float, y: Float) -> Self {
+        self.fp.center_x = x;
+        self.fp.center_y = y;
+        self
+    }
+
+    /// The shorter side of the image spans `2 / zoom`, 0.5 shows the whole set
+    pub fn zoom(mut self, zoom: Float) -> Self {
+        self.fp.zoom = zoom;
+        self
+    }
+
+    pub fn iterations(mut self, max_iter: Float) -> Self {
+        self.fp.max_iter = max_iter;
+        self
+    }
+
+    /// Samples per pixel along each axis
+    pub fn supersampling(mut self, ss_factor: i32) -> Self {
+        self.fp.ss_factor = ss_factor;
+        self
+    }
+
+    /// Counter-clockwise rotation in degrees
+    pub fn rotation(mut self, degrees: Float) -> Self {
+        self.fp.rotation = degrees;
+        self
+    }
+
+    pub fn colors(mut self, offset: Float, saturation: Float) -> Self {
+        self.fp.color_offset = offset;
+        self.fp.color_saturation = saturation;
+        self
+    }
+
+    pub fn build(self) -> Result<FractalRenderer, String> {
+        validate(self.width, self.height, &self.fp)?;
+        Ok(FractalRenderer {
+            renderer: Renderer::default(),
+            backend: self.backend,
+            width: self.width,
+            height: self.height,
+            fp: self.fp,
+        })
+    }
+
+    /// Render on a thread of its own. The returned task streams the tiles as they finish and
+    /// resolves to the image, both as a future and with [`RenderTask::wait`].
+    pub fn spawn(self) -> Result<RenderTask, String> {
+        // Checked here so that invalid settings fail right away
+        self.clone().build()?;
+        let (sender, events) = unbounded();
+        let shared = Arc::new(Mutex::new(TaskState::default()));
+        let state = shared.clone();
+        let thread = thread::spawn(move || {
+            let total_pixels = self.width as u64 * self.height as u64;
+            let completed = AtomicU64::new(0);
+            let result = self.build().and_then(|mut renderer| {
+                renderer.render_tiles(|tile| {
+                    let pixels = tile.width as u64 * tile.height as u64;
+                    let completed_pixels = completed.fetch_add(pixels, Ordering::Relaxed) + pixels;
+                    // The task may have been dropped, the render finishes anyway
+                    let _ = sender.send(RenderEvent::Tile(tile));
+                    let _ = sender.send(RenderEvent::Progress {
+                        completed_pixels,
+                        total_pixels,
+                    });
+                })
+            });
+            let mut state = state.lock().unwrap();
+            state.result = Some(result);
+            if let Some(waker) = state.waker.take() {
+                waker.wake();
+            }
+        });
+        Ok(RenderTask {
+            events,
+            shared,
+            thread: Some(thread),
+        })
+    }
+}
+
+fn validate(width: u32, height: u32, fp: &FractalProperties) -> Result<(), String> {
+    if width == 0 || height == 0 {
+        return Err("Image size must not be empty".to_string());
+    }
+    fp.validate()
+}
+
+/// Renders views of the Mandelbrot set into images. Keeps the OpenCL program built between
+/// renders, so reuse it instead of building a new one for every image.
+pub struct FractalRenderer {
+    renderer: Renderer,
+    backend: Backend,
+    width: u32,
+    height: u32,
+    fp: FractalProperties,
+}
+
+impl FractalRenderer {
+    pub fn builder() -> RendererBuilder {
+        RendererBuilder::default()
+    }
+
+    /// Backend used for the next render, `Auto` turns into `Cpu` once OpenCL failed
+    pub fn backend(&self) -> Backend {
+        self.backend
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    pub fn set_size(&mut self, width: u32, height: u32) {
+        self.width = width;
+        self.height = height;
+    }
+
+    pub fn properties(&self) -> FractalProperties {
+        self.fp
+    }
+
+    pub fn set_properties(&mut self, fp: FractalProperties) {
+        self.fp = fp;
+    }
+
+    pub fn render(&mut self) -> Result<RgbImage, String> {
+        self.render_tiles(|_| {})
+    }
+
+    /// Render the image as rows of RGB pixels
+    pub fn render_pixels(&mut self) -> Result<Vec<[u8; 3]>, String> {
+        self.render_with(|_| {})
+    }
+
+    /// Render the image, handing every tile to `on_tile` as soon as it's finished. Tiles can
+    /// finish on several threads at once and in any order.
+    pub fn render_tiles<F>(&mut self, on_tile: F) -> Result<RgbImage, String>
+    where
+        F: Fn(Tile) + Sync,
+    {
+        let pixels = self.render_with(on_tile)?;
+        Ok(export::to_image_buffer(self.width, self.height, &pixels).unwrap())
+    }
+
+    /// Render the image and save it in the format picked by the extension, PNGs get the view
+    /// embedded.
+    pub fn save(&mut self, path: impl AsRef<Path>) -> Result<(), String> {
+        let pixels = self.render_pixels()?;
+        let path = path.as_ref();
+        export::save_image(
+            path,
+            self.width,
+            self.height,
+            &pixels,
+            &Location::new(self.fp),
+        )
+        .map_err(|e| format!("Failed saving {}: {}", path.display(), e))
+    }
+
+    fn render_with<F>(&mut self, on_tile: F) -> Result<Vec<[u8; 3]>, String>
+    where
+        F: Fn(Tile) + Sync,
+    {
+        let on_tile = |x, y, width, height, pixels: &[[u8; 3]], _| {
+            on_tile(Tile {
+                x,
+                y,
+                width,
+                height,
+                pixels: pixels.to_vec(),
+            })
+        };
+        let (width, height, fp) = (self.width, self.height, self.fp);
+        validate(width, height, &fp)?;
+        let algorithm = match self.backend {
+            #[cfg(feature = "opencl")]
+            Backend::Auto => {
+                match self
+                    .renderer
+                    .render_tiled(&AlgorithmType::OpenCL, width, height, fp, on_tile)
+                {
+                    Ok((pixels, _)) => return Ok(pixels),
+                    Err(e) => {
+                        println!("OpenCL failed ({}), rendering on the CPU", e);
+                        self.backend = Backend::Cpu;
+                        AlgorithmType::NaiveCPU
+                    }
+                }
+            }
+            #[cfg(not(feature = "opencl"))]
+            Backend::Auto => AlgorithmType::NaiveCPU,
+            Backend::Cpu => AlgorithmType::NaiveCPU,
+            #[cfg(feature = "opencl")]
+            Backend::OpenCL => AlgorithmType::OpenCL,
+        };
+        Ok(self
+            .renderer
+            .render_tiled(&algorithm, width, height, fp, on_tile)?
+            .0)
+    }
+}
+
+#[derive(Default)]
+struct TaskState {
+    result: Option<Result<RgbImage, String>>,
+    waker: Option<Waker>,
+}
+
+/// Render running on its own thread, see [`RendererBuilder::spawn`]. Iterating over it yields
+/// the [`RenderEvent`]s until the render is done, awaiting it or calling [`RenderTask::wait`]
+/// gives the image. Events that are never read are kept until the task is dropped.
+pub struct RenderTask {
+    events: Receiver<RenderEvent>,
+    shared: Arc<Mutex<TaskState>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl RenderTask {
+    /// Receiver of the events, for use with `crossbeam_channel::select!`
+    pub fn events(&self) -> &Receiver<RenderEvent> {
+        &self.events
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.shared.lock().unwrap().result.is_some()
+    }
+
+    /// Block until the render is done
+    pub fn wait(mut self) -> Result<RgbImage, String> {
+        if let Some(thread) = self.thread.take() {
+            thread
+                .join()
+                .map_err(|_| "The render thread panicked".to_string())?;
+        }
+        let result = self.shared.lock().unwrap().result.take();
+        result.unwrap_or_else(|| Err("The render was already taken".to_string()))
+    }
+}
+
+impl Iterator for RenderTask {
+    type Item = RenderEvent;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.events.recv().ok()
+    }
+}
+
+impl Future for RenderTask {
+    type Output = Result<RgbImage, String>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = self.shared.lock().unwrap();
+        match state.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
@@ -1,7 +1,8 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")] // hide console window on Windows in release
 
 use std::{
-    thread::sleep,
+    path::PathBuf,
+    thread::{self, sleep},
     time::{Duration, Instant},
 };
 
@@ -10,23 +11,45 @@ use eframe::{
     egui,
     epi::{App, Frame},
 };
-use egui::{Color32, TextureHandle};
-use fontdue::{
-    layout::{Layout, LayoutSettings, TextStyle},
-    Font,
-};
+use egui::{Color32, Key, TextureHandle};
 use image::{imageops::FilterType, ImageBuffer};
 
-use crate::{
-    algorithms::mandelbrot::{map_to_complex_plane, AlgorithmType, Float, FractalProperties},
-    renderer::{renderer_thread, RendererMessage},
+use brot_rs::{
+    algorithms::mandelbrot::{AlgorithmType, Float, FractalProperties, ViewTransform},
+    animation::AnimationFormat,
+    batch::Renderer,
+    cycle::{self, CycleSettings},
+    export,
+    history::History,
+    import,
+    location::Location,
+    overlay::{Anchor, Overlay},
+    renderer::{renderer_thread, RenderProgress, RendererMessage},
+    reproject::{Keyframe, ZoomKeyframes},
+    timeline::{Interpolation, Property, Timeline},
+    video::{Blend, VideoJob, VideoSettings, VideoWriter},
 };
 
-/// Split up every rendered frame into scaled sub-frames
-const FRAMES_PER_IMG: usize = 1;
-
 const FONT_DATA: &[u8] = include_bytes!("../font.ttf");
 
+/// File the navigation history is saved to, so it can be restored after a restart
+const HISTORY_FILE: &str = "history.json";
+
+/// Animation the Cycle colors button writes
+const CYCLE_FILE: &str = "cycle.gif";
+
+/// File the keys of the video timeline are saved to and loaded from
+const TIMELINE_FILE: &str = "timeline.json";
+
+/// Location file the Save/Load location buttons default to
+const LOCATION_FILE: &str = "location.json";
+
+/// Scrolling this far zooms by one step, it's what egui reports for a notch of a mouse wheel
+const SCROLL_STEP_POINTS: f32 = 50.0;
+
+/// Zoom factor of one scroll step
+const SCROLL_ZOOM_STEP: Float = 1.25;
+
 pub fn run_gui() {
     let (renderer_sender, gui_receiver) = renderer_thread();
 
@@ -40,56 +63,139 @@ struct MyApp {
     gui_receiver: Receiver<RendererMessage>,
     img_handle: Option<TextureHandle>,
     img_data: Option<Vec<[u8; 3]>>,
+    /// Size of the image in `img_data`
+    img_size: (u32, u32),
+    /// Size of the image area, renders are done at this size
+    view_size: (u32, u32),
     fp: FractalProperties,
     video_render: Option<VideoRender>,
     render_algorithm: AlgorithmType,
+    /// Set while a render command was sent and its final image hasn't arrived yet
+    render_progress: Option<RenderProgress>,
+    /// Id of the last render command, tiles and progress of older renders are dropped
+    render_id: u64,
+    /// The view changed while a render was in flight, render it once that one is finished
+    render_queued: bool,
+    /// The view the last finished image was rendered with
+    rendered_fp: FractalProperties,
+    /// Last finished image and its view, shown scaled while the current view is rendering
+    preview: Option<(TextureHandle, FractalProperties)>,
+    /// Pointer position and view transform at the start of a pan
+    pan_start: Option<(egui::Pos2, ViewTransform)>,
+    /// Pointer position and view rotation at the start of a rotation
+    rotate_start: Option<(egui::Pos2, Float)>,
+    /// Pointer position at the start of a box zoom
+    box_zoom_start: Option<egui::Pos2>,
+    history: History,
+    /// Settings of the next video, the end zoom is taken from the current view
+    video_settings: VideoSettings,
+    /// Directory the next video is saved to, as typed in
+    video_dir: String,
+    /// Animation the frames of the next video are also encoded into
+    video_animation: Option<AnimationFormat>,
+    /// Text drawn onto video frames and saved images
+    overlay: Overlay,
+    overlay_enabled: bool,
+    /// Also draw the overlay onto saved images, off so that they stay plain like before
+    overlay_on_images: bool,
+    /// Keyed animation of the next video
+    timeline: Timeline,
+    /// Time and interpolation of the next key
+    key_time: Float,
+    key_interpolation: Interpolation,
+    /// Path the location is saved to and loaded from
+    location_path: String,
+    /// Why the last location couldn't be saved or loaded, shown next to the buttons
+    location_error: Option<String>,
 }
 
 struct VideoRender {
+    /// Writes the frames, its settings are fixed once the video started
+    writer: VideoWriter,
+    /// View the video was started from, frames change the animated properties of it
+    base_fp: FractalProperties,
+    /// Set when the frames are reprojected from keyframes
+    keyframes: Option<ZoomKeyframes>,
+    /// Motion blur sub-frame being rendered, and the ones rendered so far
+    sample: u32,
+    blend: Blend,
     current_frame: u32,
-    max_zoom: Float,
+    total_frames: u32,
+    /// Frame the render started or resumed at
+    first_frame: u32,
     render_started: Instant,
-    font: Font,
-    layout: Layout,
 }
 
-impl Default for VideoRender {
-    fn default() -> Self {
+impl VideoRender {
+    fn new(writer: VideoWriter) -> Self {
+        let settings = &writer.settings;
         Self {
-            current_frame: 0,
-            max_zoom: 10000000000.0,
+            base_fp: writer.fp,
+            keyframes: settings.reproject.then(|| settings.keyframes()),
+            sample: 0,
+            blend: Blend::default(),
+            total_frames: settings.total_frames(),
+            current_frame: writer.next_frame(),
+            first_frame: writer.next_frame(),
             render_started: Instant::now(),
-            font: fontdue::Font::from_bytes(FONT_DATA, fontdue::FontSettings::default())
-                .expect("Failed loading in font"),
-            layout: Layout::new(fontdue::layout::CoordinateSystem::PositiveYDown),
+            writer,
         }
     }
 }
 
 impl MyApp {
     fn new(s: Sender<RendererMessage>, r: Receiver<RendererMessage>) -> Self {
+        let history = History::load_or_default(HISTORY_FILE);
         Self {
             renderer_sender: s,
             gui_receiver: r,
             img_handle: None,
             img_data: None,
-            fp: FractalProperties::default(),
+            img_size: (0, 0),
+            view_size: (0, 0),
+            fp: history.current(),
             video_render: None,
             #[cfg(not(feature = "opencl"))]
             render_algorithm: AlgorithmType::NaiveCPU,
             #[cfg(feature = "opencl")]
             render_algorithm: AlgorithmType::OpenCL,
+            render_progress: None,
+            render_id: 0,
+            render_queued: false,
+            rendered_fp: FractalProperties::default(),
+            preview: None,
+            pan_start: None,
+            rotate_start: None,
+            box_zoom_start: None,
+            history,
+            video_dir: VideoSettings::default().output_dir.display().to_string(),
+            video_settings: VideoSettings::default(),
+            video_animation: None,
+            overlay: Overlay::default(),
+            overlay_enabled: true,
+            overlay_on_images: false,
+            timeline: Timeline::default(),
+            key_time: 0.0,
+            key_interpolation: Interpolation::ExponentialZoom,
+            location_path: LOCATION_FILE.to_string(),
+            location_error: None,
         }
     }
 }
 
 impl App for MyApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut Frame) {
-        egui::CentralPanel::default().show(ctx, |ui| {
-            let (width, height) = (1280, 720);
-            let (scaled_width, scaled_height) = (1280, 720);
+        egui::TopBottomPanel::bottom("status_bar").show(ctx, |ui| {
+            self.status_ui(ui);
+        });
 
-            assert!(width >= scaled_width && height >= scaled_height);
+        egui::CentralPanel::default().show(ctx, |ui| {
+            let (width, height) = match &self.video_render {
+                // Video frames keep the size the video was started with
+                Some(vr) => (vr.writer.settings.width, vr.writer.settings.height),
+                None => self.view_size,
+            };
+            let (scaled_width, scaled_height) = (width, height);
 
             if self.video_render.is_none() {
                 ui.horizontal(|ui| {
@@ -140,6 +246,15 @@ impl App for MyApp {
                         self.refresh_img(width, height);
                     }
                 });
+                ui.horizontal(|ui| {
+                    ui.label("rotation: ")
+                        .on_hover_text("Alt + drag on the image to rotate");
+                    ui.add(egui::Slider::new(&mut self.fp.rotation, -180.0..=180.0).suffix("°"));
+                    ui.label("skew: ");
+                    ui.add(egui::Slider::new(&mut self.fp.skew, -2.0..=2.0));
+                    ui.label("stretch: ");
+                    ui.add(egui::Slider::new(&mut self.fp.stretch, 0.1..=10.0).logarithmic(true));
+                });
             }
 
             ui.horizontal(|ui| {
@@ -151,123 +266,206 @@ impl App for MyApp {
                     && ui.button("Save image").clicked()
                     && self.img_data.is_some()
                 {
-                    self.save_img(width, height, "screenshot.bmp");
+                    self.save_img(self.img_size.0, self.img_size.1, "screenshot.png");
                 }
 
+                if self.video_render.is_none() {
+                    self.location_ui(ui, width, height);
+                }
+
+                if self.video_render.is_none() {
+                    self.video_settings_ui(ui);
+                }
                 if ui.button("Render video").clicked() {
                     if self.video_render.is_none() {
-                        self.video_render = Some(VideoRender {
-                            max_zoom: self.fp.zoom,
-                            ..VideoRender::default()
-                        });
-                        self.fp.zoom = 0.5;
-                        self.refresh_img(width, height);
+                        self.start_video(width, height);
                     } else {
-                        self.video_render = None;
+                        self.stop_video();
                     }
                 }
+                if self.video_render.is_none()
+                    && ui
+                        .button("Resume video")
+                        .on_hover_text("Continue the stopped video in the video directory")
+                        .clicked()
+                {
+                    self.resume_video();
+                }
                 if self.video_render.is_none() {
                     ui.label("Color offset:");
                     ui.add(egui::Slider::new(&mut self.fp.color_offset, 1.0..=360.0));
                     ui.label("Saturation:");
                     ui.add(egui::Slider::new(&mut self.fp.color_saturation, 0.1..=1.0));
+                    if ui
+                        .button("Cycle colors")
+                        .on_hover_text(format!("Write a looping color cycle to {}", CYCLE_FILE))
+                        .clicked()
+                    {
+                        self.export_cycle(width, height);
+                    }
                 }
             });
 
-            if let Ok(msg) = self.gui_receiver.try_recv() {
-                if let RendererMessage::RenderedImage(img_data, width, height) = msg {
-                    self.img_data = Some(img_data);
-                    if let Some(vr) = &self.video_render {
-                        // Video is finished
-                        if self.fp.zoom >= vr.max_zoom {
-                            let vr = self.video_render.take().unwrap();
-                            println!(
-                                "Finished rendering the video in: {}s",
-                                vr.render_started.elapsed().as_secs()
-                            );
-                        } else {
-                            self.advance_video_frame(width, height);
+            if self.video_render.is_none() {
+                self.history_ui(ui, width, height);
+                ui.horizontal(|ui| self.timeline_ui(ui));
+                ui.horizontal(|ui| self.overlay_ui(ui));
+            }
+
+            while let Ok(msg) = self.gui_receiver.try_recv() {
+                match msg {
+                    RendererMessage::RenderedImage(img_data, width, height) => {
+                        if let Some(progress) = self.render_progress.take() {
+                            println!("Finished render: {}", progress.status());
+                            self.rendered_fp = progress.fp;
                         }
-                    } else {
-                        let rendering_timer = Instant::now();
+                        self.img_data = Some(img_data);
+                        self.img_size = (width, height);
+                        if self.video_render.is_some() {
+                            if std::mem::take(&mut self.render_queued) {
+                                // A render from before the video started, the first frame is queued
+                                self.render_video_frame();
+                            } else {
+                                self.advance_video_frame(width, height);
+                            }
+                        } else if width == scaled_width
+                            && height == scaled_height
+                            && self.img_handle.as_ref().map(|h| h.size())
+                                == Some([width as usize, height as usize])
+                        {
+                            // Every tile has already been patched into the texture
+                            ctx.request_repaint();
+                        } else {
+                            let rendering_timer = Instant::now();
 
-                        let img = ImageBuffer::from_fn(width, height, |x, y| {
-                            image::Rgb(self.img_data.as_ref().unwrap()[(y * width + x) as usize])
-                        });
+                            let img = ImageBuffer::from_fn(width, height, |x, y| {
+                                image::Rgb(
+                                    self.img_data.as_ref().unwrap()[(y * width + x) as usize],
+                                )
+                            });
 
-                        let resizing_timer = Instant::now();
-                        // Skip resizing if not needed
-                        let resized = if width == scaled_width && height == scaled_height {
-                            img
-                        } else {
-                            image::imageops::resize(
-                                &img,
-                                scaled_width,
-                                scaled_height,
-                                FilterType::Triangle,
-                            )
-                        };
-                        println!("Resizing took: {}ms", resizing_timer.elapsed().as_millis());
-
-                        let mut color_img = egui::ColorImage::new(
-                            [resized.width() as usize, resized.height() as usize],
-                            Color32::BLUE,
-                        );
-
-                        for x in 0..resized.width() {
-                            for y in 0..resized.height() {
-                                let res_pix = resized[(x, y)].0;
-                                color_img[(x as usize, y as usize)] =
-                                    Color32::from_rgb(res_pix[0], res_pix[1], res_pix[2]);
+                            let resizing_timer = Instant::now();
+                            // Skip resizing if not needed
+                            let resized = if width == scaled_width && height == scaled_height {
+                                img
+                            } else {
+                                image::imageops::resize(
+                                    &img,
+                                    scaled_width,
+                                    scaled_height,
+                                    FilterType::Triangle,
+                                )
+                            };
+                            println!("Resizing took: {}ms", resizing_timer.elapsed().as_millis());
+
+                            let mut color_img = egui::ColorImage::new(
+                                [resized.width() as usize, resized.height() as usize],
+                                Color32::BLUE,
+                            );
+
+                            for x in 0..resized.width() {
+                                for y in 0..resized.height() {
+                                    let res_pix = resized[(x, y)].0;
+                                    color_img[(x as usize, y as usize)] =
+                                        Color32::from_rgb(res_pix[0], res_pix[1], res_pix[2]);
+                                }
                             }
+
+                            let txt = ui.ctx().load_texture("0", color_img);
+                            self.img_handle.replace(txt);
+                            println!(
+                                "Rendered image in: {}ms",
+                                rendering_timer.elapsed().as_millis()
+                            );
+                            ctx.request_repaint();
                         }
 
-                        let txt = ui.ctx().load_texture("0", color_img);
-                        self.img_handle.replace(txt);
-                        println!(
-                            "Rendered image in: {}ms",
-                            rendering_timer.elapsed().as_millis()
-                        );
-                        ctx.request_repaint();
+                        if self.video_render.is_none() {
+                            if std::mem::take(&mut self.render_queued) {
+                                // Preview the newest finished image while the queued view renders
+                                if self.preview.is_some() {
+                                    self.preview =
+                                        self.img_handle.take().map(|h| (h, self.rendered_fp));
+                                }
+                                self.refresh_img(self.view_size.0, self.view_size.1);
+                            } else {
+                                self.preview = None;
+                                if self.pan_start.is_none() && self.rotate_start.is_none() {
+                                    self.record_history();
+                                }
+                            }
+                        }
+                    }
+                    RendererMessage::RenderedTile(id, x, y, w, h, pixels) => {
+                        let current = self.render_progress.as_ref().filter(|p| p.id == id);
+                        if let Some(size) = current.map(|p| p.size) {
+                            self.patch_tile(ctx, size, [x, y, w, h], &pixels);
+                            ctx.request_repaint();
+                        }
+                    }
+                    RendererMessage::RenderProgress(
+                        id,
+                        completed_pixels,
+                        total_pixels,
+                        iterations,
+                    ) => {
+                        let current = self.render_progress.as_mut().filter(|p| p.id == id);
+                        if let Some(progress) = current {
+                            progress.completed_pixels = completed_pixels;
+                            progress.total_pixels = total_pixels;
+                            progress.iterations = iterations;
+                        }
+                    }
+                    RendererMessage::RenderFailed(id, e) => {
+                        if self.render_progress.as_ref().map(|p| p.id) == Some(id) {
+                            println!("Render failed: {}", e);
+                            self.render_progress = None;
+                            if self.video_render.is_some() {
+                                println!("Stopping the video");
+                                self.stop_video();
+                                self.render_queued = false;
+                            } else if std::mem::take(&mut self.render_queued) {
+                                self.refresh_img(self.view_size.0, self.view_size.1);
+                            }
+                        }
+                    }
+                    RendererMessage::RenderCommand(..) => {
+                        panic!("Received invalid renderer message");
                     }
-                } else {
-                    panic!("Received invalid renderer message");
                 }
             }
 
-            if self.img_handle.is_none() {
+            if self.img_handle.is_none() && self.preview.is_none() {
                 self.img_handle = Some(ui.ctx().load_texture("0", egui::ColorImage::example()));
-                self.refresh_img(width, height);
             }
 
-            let img = ui.add(
-                egui::ImageButton::new(
-                    self.img_handle.as_ref().unwrap().id(),
-                    self.img_handle.as_ref().unwrap().size_vec2(),
-                )
-                .frame(false),
-            );
+            // Render at the size of the panel, the first frame and every resize start a render
+            let available = ui.available_size();
+            let view_size = (available.x.max(1.0) as u32, available.y.max(1.0) as u32);
+            if self.video_render.is_none() && view_size != self.view_size {
+                self.view_size = view_size;
+                self.navigate(view_size.0, view_size.1);
+            }
 
-            if img.clicked() {
-                let mut loc = img.interact_pointer_pos().unwrap();
-                loc.x -= img.rect.left().max(0f32).min(width as f32);
-                loc.y -= img.rect.top().max(0f32).min(height as f32);
-                let displayed_img_size = self.img_handle.as_ref().unwrap().size_vec2();
-                // Calculate clicked point on the complex plane
-                self.fp.center_x = map_to_complex_plane(
-                    loc.x as Float,
-                    displayed_img_size.x as Float,
-                    self.fp.center_x,
-                    self.fp.zoom,
-                );
-                self.fp.center_y = map_to_complex_plane(
-                    loc.y as Float,
-                    displayed_img_size.y as Float,
-                    self.fp.center_y,
-                    self.fp.zoom,
-                );
-                self.fp.zoom *= 2 as Float;
-                self.refresh_img(width, height);
+            // Exactly the size that is rendered, so that screen and image pixels line up
+            let size = egui::vec2(self.view_size.0 as f32, self.view_size.1 as f32);
+            let (rect, img) = ui.allocate_exact_size(size, egui::Sense::click_and_drag());
+            let painter = ui.painter_at(rect);
+            if let Some((preview, preview_fp)) = &self.preview {
+                let preview_quad = self.view_quad(rect, preview_fp, preview.size());
+                paint_texture(&painter, preview, preview_quad);
+            }
+            if let Some(img_handle) = &self.img_handle {
+                let img_fp = self
+                    .render_progress
+                    .as_ref()
+                    .map_or(self.rendered_fp, |p| p.fp);
+                let img_quad = self.view_quad(rect, &img_fp, img_handle.size());
+                paint_texture(&painter, img_handle, img_quad);
+            }
+
+            if self.video_render.is_none() {
+                self.handle_navigation(ui, &img, &painter, width, height);
             }
         });
 
@@ -275,131 +473,731 @@ impl App for MyApp {
         if self.video_render.is_some() {
             sleep(Duration::from_millis(100));
             ctx.request_repaint();
+        } else if self.render_progress.is_some() {
+            // Keep polling for tiles until the render is finished
+            ctx.request_repaint();
         }
     }
 }
 
+/// Paint the whole texture stretched onto the quad with the given top-left, top-right,
+/// bottom-right and bottom-left corners
+fn paint_texture(painter: &egui::Painter, texture: &TextureHandle, corners: [egui::Pos2; 4]) {
+    let uvs = [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)];
+    let mut mesh = egui::Mesh::with_texture(texture.id());
+    for (pos, (u, v)) in corners.into_iter().zip(uvs) {
+        mesh.vertices.push(egui::epaint::Vertex {
+            pos,
+            uv: egui::pos2(u, v),
+            color: Color32::WHITE,
+        });
+    }
+    mesh.add_triangle(0, 1, 2);
+    mesh.add_triangle(0, 2, 3);
+    painter.add(egui::Shape::mesh(mesh));
+}
+
 impl MyApp {
-    /// Send a rendering request to the rendering backend.
-    fn refresh_img(&self, width: u32, height: u32) {
-        self.renderer_sender
-            .send(RendererMessage::RenderCommand(
-                width as u32,
-                height as u32,
-                self.render_algorithm.clone(),
-                self.fp.clone(),
-            ))
-            .unwrap();
+    /// Transform of the current view displayed in the image area, in screen points relative to
+    /// its top left corner
+    fn screen_transform(&self) -> ViewTransform {
+        let (width, height) = self.view_size;
+        ViewTransform::new(&self.fp, width as Float, height as Float)
     }
 
-    fn save_img(&self, width: u32, height: u32, filename: &str) {
-        let data = self.img_data.as_ref().unwrap();
-        let img = ImageBuffer::from_fn(width, height, |x, y| {
-            image::Rgb(data[(y * width + x) as usize])
-        });
-        img.save(filename).unwrap();
+    /// Screen corners that an `image_size` image rendered with `image_fp` covers when the
+    /// current view is displayed in `rect`.
+    fn view_quad(
+        &self,
+        rect: egui::Rect,
+        image_fp: &FractalProperties,
+        image_size: [usize; 2],
+    ) -> [egui::Pos2; 4] {
+        let (iw, ih) = (image_size[0] as Float, image_size[1] as Float);
+        let image_transform = ViewTransform::new(image_fp, iw, ih);
+        let screen_transform = self.screen_transform();
+        [(0.0, 0.0), (iw, 0.0), (iw, ih), (0.0, ih)].map(|(x, y)| {
+            let (cx, cy) = image_transform.to_complex(x, y);
+            let (sx, sy) = screen_transform.to_pixel(cx, cy);
+            rect.min + egui::vec2(sx as f32, sy as f32)
+        })
     }
 
-    fn advance_video_frame(&mut self, width: u32, height: u32) {
-        let vr = self.video_render.as_mut().unwrap();
+    /// Point of the complex plane under the given screen position
+    fn screen_to_complex(&self, rect: egui::Rect, pos: egui::Pos2) -> (Float, Float) {
+        let loc = pos - rect.min;
+        self.screen_transform()
+            .to_complex(loc.x as Float, loc.y as Float)
+    }
 
-        let mut img = ImageBuffer::from_fn(width, height, |x, y| {
-            image::Rgb(self.img_data.as_ref().unwrap()[(y * width + x) as usize])
-        });
+    /// Zoom by `factor` while keeping the point under `pos` in place.
+    fn zoom_at(&mut self, rect: egui::Rect, pos: egui::Pos2, factor: Float) {
+        let (cx, cy) = self.screen_to_complex(rect, pos);
+        self.fp.center_x = cx - (cx - self.fp.center_x) / factor;
+        self.fp.center_y = cy - (cy - self.fp.center_y) / factor;
+        self.fp.zoom *= factor;
+    }
 
-        let text_timer = Instant::now();
-        // TEXT
-        const FONT_SIZE: f32 = 50.0;
-        vr.layout.append(
-            &[&vr.font],
-            &TextStyle::new(&self.fp.zoom.round().to_string(), FONT_SIZE, 0),
-        );
-        for glyph in vr.layout.glyphs() {
-            if glyph.char_data.rasterize() {
-                let (metrics, bmp) = vr.font.rasterize(glyph.parent, FONT_SIZE);
-                for (i, p) in bmp.iter().enumerate() {
-                    // Skip black pixels
-                    if p == &255 {
-                        let x = (i % metrics.width) + glyph.x as usize + 30;
-                        let y = (i / metrics.width) + glyph.y as usize + 30;
-                        img[(x as u32, y as u32)] = image::Rgb([*p, *p, *p]);
+    /// Handle clicking, dragging and scrolling on the displayed image.
+    fn handle_navigation(
+        &mut self,
+        ui: &egui::Ui,
+        img: &egui::Response,
+        painter: &egui::Painter,
+        width: u32,
+        height: u32,
+    ) {
+        let rect = img.rect;
+        let fp_was = self.fp;
+        let (modifiers, press_origin, scroll, pinch) = {
+            let input = ui.input();
+            (
+                input.modifiers,
+                input.pointer.press_origin(),
+                input.scroll_delta.y,
+                input.zoom_delta(),
+            )
+        };
+
+        if img.drag_started() {
+            let origin = press_origin.unwrap_or(rect.center());
+            if modifiers.shift {
+                self.box_zoom_start = Some(origin);
+            } else if modifiers.alt {
+                self.rotate_start = Some((origin, self.fp.rotation));
+            } else {
+                self.pan_start = Some((origin, self.screen_transform()));
+            }
+        }
+
+        if let Some(pos) = img.interact_pointer_pos() {
+            if let Some(start) = self.box_zoom_start {
+                let selection = egui::Rect::from_two_pos(start, pos);
+                painter.rect_stroke(selection, 0.0, egui::Stroke::new(1.0, Color32::WHITE));
+                if img.drag_released() {
+                    self.box_zoom_start = None;
+                    // Ignore tiny selections, those are most likely misclicks
+                    if selection.width() > 4.0 && selection.height() > 4.0 {
+                        let (cx, cy) = self.screen_to_complex(rect, selection.center());
+                        self.fp.center_x = cx;
+                        self.fp.center_y = cy;
+                        self.fp.zoom *= (rect.width() / selection.width())
+                            .min(rect.height() / selection.height())
+                            as Float;
                     }
                 }
+            } else if let Some((start, rotation)) = self.rotate_start {
+                // Rotate around the center of the view, following the pointer
+                let angle = |p: egui::Pos2| (p - rect.center()).angle() as Float;
+                let rotation = rotation - (angle(pos) - angle(start)).to_degrees();
+                self.fp.rotation = (rotation + 180.0).rem_euclid(360.0) - 180.0;
+            } else if let Some((start, transform)) = self.pan_start {
+                // Only move by whole pixels, so the renderer can reuse the previous image
+                let delta = pos - start;
+                let (cx, cy) = transform.to_complex(
+                    self.view_size.0 as Float / 2.0 - delta.x.round() as Float,
+                    self.view_size.1 as Float / 2.0 - delta.y.round() as Float,
+                );
+                self.fp.center_x = cx;
+                self.fp.center_y = cy;
+            }
+
+            if img.clicked() {
+                // Recenter on the clicked point
+                let (cx, cy) = self.screen_to_complex(rect, pos);
+                self.fp.center_x = cx;
+                self.fp.center_y = cy;
+                if modifiers.command {
+                    self.fp.zoom /= 2 as Float;
+                } else {
+                    self.fp.zoom *= 2 as Float;
+                }
+            } else if img.secondary_clicked() {
+                let (cx, cy) = self.screen_to_complex(rect, pos);
+                self.fp.center_x = cx;
+                self.fp.center_y = cy;
+                self.fp.zoom /= 2 as Float;
             }
         }
-        vr.layout.reset(&LayoutSettings::default());
-        println!(
-            "Rendering text took: {}ms",
-            text_timer.elapsed().as_millis()
-        );
 
-        img.save(&format!("O:\\tmp\\video{}.bmp", vr.current_frame))
-            .expect("Failed saving video frame");
-        vr.current_frame += 1;
+        if !img.dragged() {
+            let was_dragging = self.pan_start.is_some() || self.rotate_start.is_some();
+            self.pan_start = None;
+            self.box_zoom_start = None;
+            self.rotate_start = None;
+            // Renders that finished during the drag were not recorded
+            if was_dragging && self.render_progress.is_none() && self.fp == self.rendered_fp {
+                self.record_history();
+            }
+        }
 
-        self.fp.zoom *= 1.05;
+        if let Some(pos) = img.hover_pos() {
+            // Some devices report a lot more per frame than a wheel notch, zoom one step at most
+            let steps = (scroll / SCROLL_STEP_POINTS).clamp(-1.0, 1.0);
+            let factor = SCROLL_ZOOM_STEP.powf(steps as Float) * pinch as Float;
+            if factor != 1.0 {
+                self.zoom_at(rect, pos, factor);
+            }
+        }
 
-        self.refresh_img(width, height);
+        if self.fp != fp_was {
+            self.navigate(width, height);
+        }
+    }
+
+    /// Progress of the current render and video. The bar is always shown, so that the height
+    /// of the image area doesn't change whenever a render starts or finishes.
+    fn status_ui(&mut self, ui: &mut egui::Ui) {
+        if let Some(vr) = &self.video_render {
+            let done = vr.current_frame as f32 / vr.total_frames.max(1) as f32;
+            let elapsed = vr.render_started.elapsed().as_secs_f32();
+            let rendered = vr.current_frame - vr.first_frame;
+            let eta = if rendered == 0 {
+                "-".to_string()
+            } else {
+                let remaining = vr.total_frames - vr.current_frame;
+                format!("{:.0}s", elapsed / rendered as f32 * remaining as f32)
+            };
+            ui.add(egui::ProgressBar::new(done).text(format!(
+                "Frame {}/{}, elapsed: {:.0}s, ETA: {}",
+                vr.current_frame, vr.total_frames, elapsed, eta
+            )));
+        }
+        ui.horizontal(|ui| match &self.render_progress {
+            Some(progress) => {
+                ui.add(
+                    egui::ProgressBar::new(progress.fraction())
+                        .desired_width(300.0)
+                        .show_percentage(),
+                );
+                ui.label(progress.status());
+            }
+            None => {
+                ui.add(egui::ProgressBar::new(1.0).desired_width(300.0));
+                ui.label(format!("{}x{}", self.view_size.0, self.view_size.1));
+            }
+        });
     }
 
-    // Frames are broken down into sub-frames. The sub-frames are not perfect frames, and take a lot of CPU cycles.
-    // Currently it's not worth it to use this kind of rendering, since GPU is so much faster, but it might make
-    // sense with arbitrary floating point numbers.
-    /*fn calculate_sub_frame() {
-        let desired_size = self.img_handle.clone().unwrap().size_vec2();
-        let next_zoom = self.fp.zoom * 2f64;
-        let zoom_diff = self.fp.zoom / next_zoom;
-        for i in 0..FRAMES_PER_IMG {
-            let curr_scale = 1f64 - (zoom_diff / FRAMES_PER_IMG as f64) * i as f64;
-            let new_width = width as f64 * curr_scale;
-            let new_height = height as f64 * curr_scale;
-
-            let x_offs = ((width as f32 - new_width as f32) / 2.0).ceil() as u32;
-            let y_offs = ((height as f32 - new_height as f32) / 2.0).ceil() as u32;
-            let cropped = image::imageops::crop_imm(
-                &img,
-                x_offs,
-                y_offs,
-                new_width.floor() as u32,
-                new_height.floor() as u32,
+    /// Back/forward buttons, keyboard shortcuts and a breadcrumb list of the visited views
+    fn history_ui(&mut self, ui: &mut egui::Ui, width: u32, height: u32) {
+        let (undo, redo) = {
+            let input = ui.input();
+            let m = input.modifiers;
+            (
+                (m.command && !m.shift && input.key_pressed(Key::Z))
+                    || (m.alt && input.key_pressed(Key::ArrowLeft)),
+                (m.command && input.key_pressed(Key::Y))
+                    || (m.command && m.shift && input.key_pressed(Key::Z))
+                    || (m.alt && input.key_pressed(Key::ArrowRight)),
             )
-            .to_image();
-            let mut resized = image::imageops::resize(
-                &cropped,
-                desired_size.x as u32,
-                desired_size.y as u32,
-                FilterType::Lanczos3,
+        };
+
+        let mut restore = None;
+        ui.horizontal(|ui| {
+            if ui
+                .add_enabled(self.history.can_undo(), egui::Button::new("< Back"))
+                .on_hover_text("Ctrl+Z / Alt+Left")
+                .clicked()
+                || (undo && self.history.can_undo())
+            {
+                restore = Some(self.history.current_index() - 1);
+            }
+            if ui
+                .add_enabled(self.history.can_redo(), egui::Button::new("Forward >"))
+                .on_hover_text("Ctrl+Y / Alt+Right")
+                .clicked()
+                || (redo && self.history.can_redo())
+            {
+                restore = Some(self.history.current_index() + 1);
+            }
+
+            egui::ScrollArea::horizontal()
+                .stick_to_right()
+                .show(ui, |ui| {
+                    ui.horizontal(|ui| {
+                        for (i, fp) in self.history.states().iter().enumerate() {
+                            if i > 0 {
+                                ui.label(">");
+                            }
+                            let selected = i == self.history.current_index();
+                            let label = format!("{:.2e}x", fp.zoom);
+                            if ui
+                                .selectable_label(selected, label)
+                                .on_hover_text(format!("{}, {}", fp.center_x, fp.center_y))
+                                .clicked()
+                            {
+                                restore = Some(i);
+                            }
+                        }
+                    });
+                });
+        });
+
+        if let Some(fp) = restore.and_then(|i| self.history.go_to(i)) {
+            self.fp = fp;
+            self.save_history();
+            self.navigate(width, height);
+        }
+    }
+
+    /// Output directory, frame rate, duration and start zoom of the next video.
+    fn video_settings_ui(&mut self, ui: &mut egui::Ui) {
+        let settings = &mut self.video_settings;
+        ui.label("Video:");
+        ui.add(egui::TextEdit::singleline(&mut self.video_dir).desired_width(80.0))
+            .on_hover_text("Output directory of the frames");
+        ui.add(
+            egui::DragValue::new(&mut settings.fps)
+                .clamp_range(1.0..=240.0)
+                .suffix(" fps"),
+        );
+        ui.add(
+            egui::DragValue::new(&mut settings.duration)
+                .clamp_range(0.1..=3600.0)
+                .suffix(" s"),
+        );
+        ui.add(
+            egui::DragValue::new(&mut settings.start_zoom)
+                .clamp_range(1e-3..=1e15)
+                .speed(0.01)
+                .prefix("from zoom "),
+        )
+        .on_hover_text("The video zooms from here to the current zoom");
+
+        let label = |format: Option<AnimationFormat>| match format {
+            None => "frames only",
+            Some(AnimationFormat::Gif) => "+ GIF",
+            Some(AnimationFormat::Apng) => "+ APNG",
+            Some(AnimationFormat::Y4m) => "+ Y4M",
+        };
+        egui::ComboBox::from_id_source("video_animation")
+            .selected_text(label(self.video_animation))
+            .show_ui(ui, |ui| {
+                for format in [
+                    None,
+                    Some(AnimationFormat::Gif),
+                    Some(AnimationFormat::Apng),
+                    Some(AnimationFormat::Y4m),
+                ] {
+                    ui.selectable_value(&mut self.video_animation, format, label(format));
+                }
+            });
+        ui.add(
+            egui::DragValue::new(&mut self.video_settings.motion_samples)
+                .clamp_range(1..=64)
+                .prefix("blur "),
+        )
+        .on_hover_text("Sub-frames blended into every frame");
+        ui.add(
+            egui::DragValue::new(&mut self.video_settings.shutter_angle)
+                .clamp_range(0.0..=360.0)
+                .suffix("°"),
+        )
+        .on_hover_text("Shutter angle of the motion blur");
+        ui.checkbox(&mut self.video_settings.reproject, "Reproject")
+            .on_hover_text(
+                "Render a keyframe at every doubling of the zoom and reproject the frames from them",
             );
+    }
 
-            // TEXT
-            let curr_zoom =
-                self.fp.zoom + (next_zoom - self.fp.zoom) / FRAMES_PER_IMG as f64 * i as f64;
-            const FONT_SIZE: f32 = 50.0;
-            layout.append(
-                &[&vr.font],
-                &TextStyle::new(&curr_zoom.round().to_string(), FONT_SIZE, 0),
+    /// Text drawn onto video frames and saved images.
+    fn overlay_ui(&mut self, ui: &mut egui::Ui) {
+        let overlay = &mut self.overlay;
+        ui.checkbox(&mut self.overlay_enabled, "Overlay");
+        ui.checkbox(&mut self.overlay_on_images, "On saved images");
+        ui.add(egui::TextEdit::singleline(&mut overlay.template).desired_width(200.0))
+            .on_hover_text(
+                "{zoom}, {iterations}, {center}, {rotation}, {time} and {frame} are filled in",
             );
-            for glyph in layout.glyphs() {
-                if glyph.char_data.rasterize() {
-                    let (metrics, bmp) = vr.font.rasterize(glyph.parent, FONT_SIZE);
-                    for (i, p) in bmp.iter().enumerate() {
-                        // Skip black pixels
-                        if p == &255 {
-                            let x = (i % metrics.width) + glyph.x as usize + 30;
-                            let y = (i / metrics.width) + glyph.y as usize + 30;
-                            resized[(x as u32, y as u32)] = image::Rgb([*p, *p, *p]);
-                        }
+        let label = |anchor| match anchor {
+            Anchor::TopLeft => "top left",
+            Anchor::TopRight => "top right",
+            Anchor::BottomLeft => "bottom left",
+            Anchor::BottomRight => "bottom right",
+        };
+        egui::ComboBox::from_id_source("overlay_anchor")
+            .selected_text(label(overlay.anchor))
+            .show_ui(ui, |ui| {
+                for anchor in [
+                    Anchor::TopLeft,
+                    Anchor::TopRight,
+                    Anchor::BottomLeft,
+                    Anchor::BottomRight,
+                ] {
+                    ui.selectable_value(&mut overlay.anchor, anchor, label(anchor));
+                }
+            });
+        ui.add(
+            egui::DragValue::new(&mut overlay.size)
+                .clamp_range(4.0..=500.0)
+                .suffix(" px"),
+        );
+        ui.color_edit_button_srgba_unmultiplied(&mut overlay.color);
+    }
+
+    /// Keys of the animation rendered by the next video, instead of a straight zoom.
+    fn timeline_ui(&mut self, ui: &mut egui::Ui) {
+        let keys = self
+            .timeline
+            .track(Property::Zoom)
+            .map_or(0, |t| t.keys.len());
+        ui.label(format!("Keys: {}", keys));
+        ui.add(
+            egui::DragValue::new(&mut self.key_time)
+                .clamp_range(0.0..=3600.0)
+                .speed(0.1)
+                .prefix("at ")
+                .suffix(" s"),
+        );
+        let label = |interpolation| match interpolation {
+            Interpolation::Linear => "linear",
+            Interpolation::Smoothstep => "smoothstep",
+            Interpolation::CatmullRom => "Catmull-Rom",
+            Interpolation::ExponentialZoom => "exponential zoom",
+        };
+        egui::ComboBox::from_id_source("key_interpolation")
+            .selected_text(label(self.key_interpolation))
+            .show_ui(ui, |ui| {
+                for interpolation in [
+                    Interpolation::Linear,
+                    Interpolation::Smoothstep,
+                    Interpolation::CatmullRom,
+                    Interpolation::ExponentialZoom,
+                ] {
+                    ui.selectable_value(
+                        &mut self.key_interpolation,
+                        interpolation,
+                        label(interpolation),
+                    );
+                }
+            });
+        if ui
+            .button("Key view")
+            .on_hover_text("Key the current view, videos render the keys instead of a zoom")
+            .clicked()
+        {
+            self.timeline
+                .key_view(&self.fp, self.key_time, self.key_interpolation);
+            self.key_time += 1.0;
+        }
+        if ui.button("Clear keys").clicked() {
+            self.timeline = Timeline::default();
+            self.key_time = 0.0;
+        }
+        if ui.button("Save keys").clicked() {
+            match self.timeline.write(TIMELINE_FILE) {
+                Ok(()) => println!("Saved keys to {}", TIMELINE_FILE),
+                Err(e) => println!("Failed saving keys: {}", e),
+            }
+        }
+        if ui.button("Load keys").clicked() {
+            match Timeline::read(TIMELINE_FILE) {
+                Ok(timeline) => {
+                    self.key_time = timeline.duration() + 1.0;
+                    self.timeline = timeline;
+                }
+                Err(e) => println!("Failed loading keys from {}: {}", TIMELINE_FILE, e),
+            }
+        }
+    }
+
+    /// Write a colour cycling animation of the current view in the background.
+    fn export_cycle(&self, width: u32, height: u32) {
+        let settings = CycleSettings {
+            output: CYCLE_FILE.into(),
+            width,
+            height,
+            ..CycleSettings::default()
+        };
+        let (fp, algorithm) = (self.fp, self.render_algorithm.clone());
+        thread::spawn(move || {
+            let result =
+                cycle::export_cycle(&settings, fp, &algorithm, &mut Renderer::default(), |_| {});
+            match result {
+                Ok(()) => println!("Saved color cycle to {}", CYCLE_FILE),
+                Err(e) => println!("Color cycle failed: {}", e),
+            }
+        });
+    }
+
+    /// Start rendering a video zooming into the current view, at the current image size.
+    fn start_video(&mut self, width: u32, height: u32) {
+        let output_dir = PathBuf::from(&self.video_dir);
+        let settings = VideoSettings {
+            animation: self
+                .video_animation
+                .map(|format| output_dir.join(format!("video.{}", format.extension()))),
+            output_dir,
+            end_zoom: self.fp.zoom,
+            timeline: Some(self.timeline.clone()).filter(|timeline| !timeline.is_empty()),
+            overlay: self.overlay_enabled.then(|| self.overlay.clone()),
+            width,
+            height,
+            ..self.video_settings.clone()
+        };
+        if let Err(e) = settings.validate() {
+            println!("Can't render the video: {}", e);
+            return;
+        }
+        match VideoWriter::new(settings, self.fp, Some(FONT_DATA)) {
+            Ok(writer) => self.video_render = Some(VideoRender::new(writer)),
+            Err(e) => {
+                println!("Can't render the video: {}", e);
+                return;
+            }
+        }
+        self.render_video_frame();
+    }
+
+    /// Continue the interrupted video in the video directory, skipping the frames on disk.
+    fn resume_video(&mut self) {
+        let path = VideoJob::path(&self.video_dir);
+        let writer = VideoJob::read(&path).and_then(|job| {
+            self.fp = job.fp;
+            VideoWriter::resume(job, Some(FONT_DATA))
+        });
+        match writer {
+            Ok(writer) if writer.next_frame() >= writer.settings.total_frames() => {
+                println!("The video in {} is already finished", self.video_dir);
+                if let Err(e) = writer.finish() {
+                    println!("Failed finishing the video: {}", e);
+                }
+                return;
+            }
+            Ok(writer) => {
+                println!(
+                    "Resuming the video at frame {}/{}",
+                    writer.next_frame() + 1,
+                    writer.settings.total_frames()
+                );
+                self.video_render = Some(VideoRender::new(writer));
+            }
+            Err(e) => {
+                println!("Can't resume the video from {}: {}", path.display(), e);
+                return;
+            }
+        }
+        self.render_video_frame();
+    }
+
+    /// Stop the video, finishing whatever was written so far.
+    fn stop_video(&mut self) {
+        if let Some(vr) = self.video_render.take() {
+            if let Err(e) = vr.writer.finish() {
+                println!("Failed finishing the video: {}", e);
+            }
+        }
+    }
+
+    /// Path field with buttons to save the current view to a location file and load it back.
+    fn location_ui(&mut self, ui: &mut egui::Ui, width: u32, height: u32) {
+        ui.add(egui::TextEdit::singleline(&mut self.location_path).desired_width(120.0));
+        if ui.button("Save location").clicked() {
+            self.location_error = match Location::new(self.fp).write(&self.location_path) {
+                Ok(()) => {
+                    println!("Saved location to {}", self.location_path);
+                    None
+                }
+                Err(e) => Some(format!("Failed saving location: {}", e)),
+            };
+        }
+        if ui
+            .button("Load location")
+            .on_hover_text(
+                "Location file, PNG saved by brot-rs, Kalles Fraktaler .kfr or XaoS .xpf",
+            )
+            .clicked()
+        {
+            self.location_error = match import::open(&self.location_path) {
+                Ok(import) => {
+                    for warning in &import.warnings {
+                        println!("{}: {}", self.location_path, warning);
                     }
+                    self.fp = import.location.properties;
+                    self.navigate(width, height);
+                    None
                 }
+                Err(e) => Some(format!("Failed loading {}: {}", self.location_path, e)),
+            };
+        }
+        if let Some(error) = &self.location_error {
+            ui.colored_label(Color32::RED, error);
+        }
+    }
+
+    /// Record the last finished view in the navigation history.
+    fn record_history(&mut self) {
+        if self.history.push(self.rendered_fp) {
+            self.save_history();
+        }
+    }
+
+    fn save_history(&self) {
+        if let Err(e) = self.history.save(HISTORY_FILE) {
+            println!("Failed saving navigation history: {}", e);
+        }
+    }
+
+    /// Render the current view, showing the last finished image scaled in the meantime.
+    fn navigate(&mut self, width: u32, height: u32) {
+        if self.preview.is_none() && self.render_progress.is_none() {
+            self.preview = self.img_handle.take().map(|h| (h, self.rendered_fp));
+        }
+        self.refresh_img(width, height);
+    }
+
+    /// Send a rendering request to the rendering backend. Only one render is kept in flight,
+    /// if one is already running the current view is rendered after it finishes.
+    fn refresh_img(&mut self, width: u32, height: u32) {
+        if self.render_progress.is_some() {
+            self.render_queued = true;
+            return;
+        }
+        self.render_id += 1;
+        self.render_progress = Some(RenderProgress::new(
+            self.render_id,
+            self.fp,
+            [width, height],
+        ));
+        self.renderer_sender
+            .send(RendererMessage::RenderCommand(
+                self.render_id,
+                width as u32,
+                height as u32,
+                self.render_algorithm.clone(),
+                self.fp.clone(),
+            ))
+            .unwrap();
+    }
+
+    /// Copy a finished tile into the displayed texture, replacing the texture first if its size
+    /// doesn't match the image being rendered.
+    fn patch_tile(
+        &mut self,
+        ctx: &egui::Context,
+        [width, height]: [u32; 2],
+        [x, y, w, h]: [u32; 4],
+        pixels: &[[u8; 3]],
+    ) {
+        let size = [width as usize, height as usize];
+        if self.img_handle.as_ref().map(|h| h.size()) != Some(size) {
+            let blank = egui::ColorImage::new(size, Color32::TRANSPARENT);
+            self.img_handle.replace(ctx.load_texture("0", blank));
+        }
+
+        let mut tile = egui::ColorImage::new([w as usize, h as usize], Color32::BLACK);
+        for (dst, p) in tile.pixels.iter_mut().zip(pixels) {
+            *dst = Color32::from_rgb(p[0], p[1], p[2]);
+        }
+        self.img_handle
+            .as_mut()
+            .unwrap()
+            .set_partial([x as usize, y as usize], tile);
+    }
+
+    /// Save the last rendered image, PNGs get the view it shows embedded in their metadata.
+    fn save_img(&self, width: u32, height: u32, filename: &str) {
+        let mut data = self.img_data.clone().unwrap();
+        if self.overlay_enabled && self.overlay_on_images {
+            match self.overlay.load_font(Some(FONT_DATA)) {
+                Ok(font) => {
+                    let text = self.overlay.text(&self.rendered_fp, None, None);
+                    self.overlay.draw(&font, &text, width, height, &mut data);
+                }
+                Err(e) => println!("Failed loading the overlay font: {}", e),
+            }
+        }
+        let location = Location::new(self.rendered_fp);
+        match export::save_image(filename, width, height, &data, &location) {
+            Ok(()) => println!("Saved image to {}", filename),
+            Err(e) => println!("Failed saving image: {}", e),
+        }
+    }
+
+    /// Save the frames of the image that just finished rendering, a frame or the keyframe the
+    /// next frames are reprojected from, and start rendering the next one.
+    fn advance_video_frame(&mut self, width: u32, height: u32) {
+        let vr = self.video_render.as_mut().unwrap();
+        if vr.keyframes.is_none() {
+            vr.blend.add(self.img_data.as_ref().unwrap());
+            vr.sample += 1;
+            let settings = &vr.writer.settings;
+            if vr.sample < settings.motion_samples {
+                // Render the next sub-frame of the motion blur
+                self.render_video_frame();
+                return;
+            }
+            vr.sample = 0;
+            let pixels = vr.blend.finish();
+            let fp = settings.frame_properties(vr.base_fp, vr.current_frame);
+            if self.save_video_frame(&pixels, fp) {
+                self.render_video_frame();
+            }
+            return;
+        }
+
+        let settings = &vr.writer.settings;
+        let keyframe = Keyframe {
+            index: settings.keyframe_index(vr.base_fp, vr.current_frame),
+            zoom: self.rendered_fp.zoom,
+            width,
+            height,
+            pixels: self.img_data.clone().unwrap(),
+        };
+        loop {
+            let vr = self.video_render.as_ref().unwrap();
+            let settings = &vr.writer.settings;
+            if settings.keyframe_index(vr.base_fp, vr.current_frame) != keyframe.index {
+                break;
             }
-            layout.reset(&LayoutSettings::default());
+            let pixels = settings.reproject_frame(&keyframe, vr.base_fp, vr.current_frame);
+            let fp = settings.frame_properties(vr.base_fp, vr.current_frame);
+            if !self.save_video_frame(&pixels, fp) {
+                return;
+            }
+        }
+        self.render_video_frame();
+    }
+
+    /// Render the next sub-frame of the video, or the keyframe it's reprojected from.
+    fn render_video_frame(&mut self) {
+        let vr = self.video_render.as_ref().unwrap();
+        let settings = &vr.writer.settings;
+        let (mut width, mut height) = (settings.width, settings.height);
+        match vr.keyframes {
+            Some(keyframes) => {
+                let index = settings.keyframe_index(vr.base_fp, vr.current_frame);
+                self.fp = keyframes.properties(vr.base_fp, index);
+                (width, height) = ZoomKeyframes::size(width, height);
+            }
+            None => {
+                let samples = settings.sample_properties(vr.base_fp, vr.current_frame);
+                self.fp = samples[vr.sample as usize];
+            }
+        }
+        self.refresh_img(width, height);
+    }
 
-            resized
-                .save(&format!("O:\\tmp\\video{}.bmp", vr.current_frame))
-                .expect("Failed saving video frame");
-            vr.current_frame += 1;
+    /// Write a finished frame of the video. Returns false once the video is finished or had to
+    /// be stopped.
+    fn save_video_frame(&mut self, pixels: &[[u8; 3]], fp: FractalProperties) -> bool {
+        let vr = self.video_render.as_mut().unwrap();
+        if let Err(e) = vr.writer.add_frame(vr.current_frame, pixels, fp) {
+            println!("Failed saving video frame, stopping the video: {}", e);
+            self.stop_video();
+            return false;
         }
+        vr.current_frame += 1;
 
-        self.fp.zoom = next_zoom;
-    }*/
+        if vr.current_frame >= vr.total_frames {
+            println!(
+                "Finished rendering the video in: {}s",
+                vr.render_started.elapsed().as_secs()
+            );
+            self.stop_video();
+            return false;
+        }
+        true
+    }
 }
@@ -0,0 +1,85 @@
+use std::{fs, io, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::algorithms::mandelbrot::FractalProperties;
+
+/// Maximum number of states kept, the oldest ones are dropped first
+pub const MAX_STATES: usize = 200;
+
+/// Undo/redo stack of the visited views.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct History {
+    states: Vec<FractalProperties>,
+    current: usize,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self {
+            states: vec![FractalProperties::default()],
+            current: 0,
+        }
+    }
+}
+
+impl History {
+    /// Load a previously saved history, falling back to an empty one if it's missing or invalid.
+    /// Every view in it has to pass [`FractalProperties::validate`], as the current one is
+    /// rendered right away.
+    pub fn load_or_default(path: impl AsRef<Path>) -> Self {
+        fs::read_to_string(path)
+            .ok()
+            .and_then(|data| serde_json::from_str::<History>(&data).ok())
+            .filter(|h| h.current < h.states.len() && h.states.len() <= MAX_STATES)
+            .filter(|h| h.states.iter().all(|fp| fp.validate().is_ok()))
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let data = serde_json::to_string_pretty(self)?;
+        fs::write(path, data)
+    }
+
+    pub fn current(&self) -> FractalProperties {
+        self.states[self.current]
+    }
+
+    pub fn current_index(&self) -> usize {
+        self.current
+    }
+
+    pub fn states(&self) -> &[FractalProperties] {
+        &self.states
+    }
+
+    /// Record a newly visited view, dropping every state that could have been redone.
+    /// Returns `false` if the view is the current state already.
+    pub fn push(&mut self, fp: FractalProperties) -> bool {
+        if self.current() == fp {
+            return false;
+        }
+        self.states.truncate(self.current + 1);
+        self.states.push(fp);
+        if self.states.len() > MAX_STATES {
+            self.states.remove(0);
+        }
+        self.current = self.states.len() - 1;
+        true
+    }
+
+    pub fn can_undo(&self) -> bool {
+        self.current > 0
+    }
+
+    pub fn can_redo(&self) -> bool {
+        self.current + 1 < self.states.len()
+    }
+
+    /// Jump to the state at `index` without dropping anything.
+    pub fn go_to(&mut self, index: usize) -> Option<FractalProperties> {
+        let fp = *self.states.get(index)?;
+        self.current = index;
+        Some(fp)
+    }
+}
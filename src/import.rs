@@ -0,0 +1,253 @@
+use std::{fs, io, path::Path};
+
+use crate::{
+    algorithms::mandelbrot::{Float, FractalProperties},
+    export,
+    location::Location,
+};
+
+/// Zoom beyond which `Float` coordinates can't tell neighbouring pixels apart anymore
+const PRECISION_LIMIT_ZOOM: Float = 1e13;
+
+const PALETTE_WARNING: &str = "Palette not supported, using the hue cycle";
+
+/// A location read from another program's file, with everything that couldn't be carried over.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Import {
+    pub location: Location,
+    pub warnings: Vec<String>,
+}
+
+impl Import {
+    fn new() -> Self {
+        Self {
+            location: Location::new(FractalProperties::default()),
+            warnings: vec![],
+        }
+    }
+
+    fn warn(&mut self, warning: impl Into<String>) {
+        let warning = warning.into();
+        if !self.warnings.contains(&warning) {
+            self.warnings.push(warning);
+        }
+    }
+
+    /// Check the imported zoom, `Float` coordinates only go that deep, and that the view can be
+    /// rendered.
+    fn check_zoom(mut self) -> io::Result<Self> {
+        let zoom = self.location.properties.zoom;
+        if !zoom.is_finite() || zoom <= 0.0 {
+            return Err(invalid_data(format!("Unsupported zoom: {}", zoom)));
+        }
+        if zoom > PRECISION_LIMIT_ZOOM {
+            self.warn(format!(
+                "Zoom {:e} is beyond double precision, the image will be pixelated",
+                zoom
+            ));
+        }
+        self.location.properties.validate().map_err(invalid_data)?;
+        Ok(self)
+    }
+}
+
+fn invalid_data(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.into())
+}
+
+fn parse_float(key: &str, value: &str) -> io::Result<Float> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| invalid_data(format!("Invalid {}: {}", key, value.trim())))
+}
+
+/// Open any file a location can be read from, picked by the extension: location JSON, PNG saved
+/// by brot-rs, Kalles Fraktaler `.kfr` or XaoS `.xpf`.
+pub fn open(path: impl AsRef<Path>) -> io::Result<Import> {
+    let path = path.as_ref();
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    match extension.as_str() {
+        "png" => Ok(Import {
+            location: export::read_png_location(path)?,
+            warnings: vec![],
+        }),
+        "kfr" => import_kfr(&fs::read_to_string(path)?),
+        "xpf" => import_xpf(&fs::read_to_string(path)?),
+        _ => Ok(Import {
+            location: Location::read(path)?,
+            warnings: vec![],
+        }),
+    }
+}
+
+/// Import a Kalles Fraktaler location, made of `Key: value` lines.
+pub fn import_kfr(data: &str) -> io::Result<Import> {
+    let mut import = Import::new();
+    let (mut has_re, mut has_im) = (false, false);
+    for line in data.lines() {
+        let (key, value) = match line.split_once(':') {
+            Some((key, value)) => (key.trim(), value.trim()),
+            None => continue,
+        };
+        let fp = &mut import.location.properties;
+        match key {
+            "Re" => {
+                fp.center_x = parse_float(key, value)?;
+                has_re = true;
+            }
+            "Im" => {
+                fp.center_y = parse_float(key, value)?;
+                has_im = true;
+            }
+            // Kalles Fraktaler shows a radius of 2 at zoom 1
+            "Zoom" => fp.zoom = parse_float(key, value)? / 2.0,
+            "Iterations" => fp.max_iter = parse_float(key, value)?,
+            "Rotate" => fp.rotation = parse_float(key, value)?,
+            "Ratio" if parse_float(key, value)? != 360.0 => {
+                import.warn(format!("Unsupported axis ratio {}, ignored", value))
+            }
+            "FractalType" if value != "0" => import.warn(format!(
+                "Unsupported fractal type {}, rendering the Mandelbrot set",
+                value
+            )),
+            "Power" if value != "2" => import.warn(format!(
+                "Unsupported power {}, rendering the Mandelbrot set",
+                value
+            )),
+            "Colors" => import.warn(PALETTE_WARNING),
+            "Slopes" if value != "0" => import.warn("Slope shading not supported"),
+            _ => {}
+        }
+    }
+    if !has_re || !has_im {
+        return Err(invalid_data("Kalles Fraktaler file has no Re/Im center"));
+    }
+    import.check_zoom()
+}
+
+/// Import a XaoS position file, a list of `(command args...)` expressions.
+pub fn import_xpf(data: &str) -> io::Result<Import> {
+    let mut import = Import::new();
+    let mut has_view = false;
+    for command in parse_commands(data)? {
+        let (name, args) = match command.split_first() {
+            Some((name, args)) => (name.as_str(), args),
+            None => continue,
+        };
+        let float_arg = |i: usize| -> io::Result<Float> {
+            let arg = args
+                .get(i)
+                .ok_or_else(|| invalid_data(format!("Missing argument for {}", name)))?;
+            parse_float(name, arg)
+        };
+        let fp = &mut import.location.properties;
+        match name {
+            "view" => {
+                // Center and the size of the visible area
+                fp.center_x = float_arg(0)?;
+                fp.center_y = float_arg(1)?;
+                let (width, height) = (float_arg(2)?, float_arg(3)?);
+                fp.zoom = 2.0 / width.min(height);
+                has_view = true;
+            }
+            "maxiter" => fp.max_iter = float_arg(0)?,
+            "angle" => fp.rotation = float_arg(0)?,
+            "formula" if args.first().map(String::as_str) != Some("'mandel") => {
+                import.warn(format!(
+                    "Unsupported formula {}, rendering the Mandelbrot set",
+                    args.join(" ")
+                ))
+            }
+            "julia" | "fastjulia" if args.first().map(String::as_str) == Some("#t") => {
+                import.warn("Julia sets are not supported")
+            }
+            "palette" | "defaultpalette" => import.warn(PALETTE_WARNING),
+            "outcoloring" | "incoloring" | "outtcoloring" | "intcoloring" | "plane"
+                if args.first().map(String::as_str) != Some("0") =>
+            {
+                import.warn(format!("Unsupported {} mode, ignored", name))
+            }
+            "cycling" if args.first().map(String::as_str) == Some("#t") => {
+                import.warn("Palette cycling not supported")
+            }
+            "filter" => import.warn(format!("Unsupported filter {}, ignored", args.join(" "))),
+            "initstate" | "formula" | "julia" | "fastjulia" | "outcoloring" | "incoloring"
+            | "outtcoloring" | "intcoloring" | "plane" | "cycling" | "cyclingspeed" | "maxstep"
+            | "speedup" | "usleep" | "periodicity" => {}
+            _ => import.warn(format!("Unknown XaoS command {}, ignored", name)),
+        }
+    }
+    if !has_view {
+        return Err(invalid_data("XaoS file has no view"));
+    }
+    import.check_zoom()
+}
+
+/// Split a XaoS file into its top-level commands, every command being a list of atoms.
+/// Nested lists are flattened into their command.
+fn parse_commands(data: &str) -> io::Result<Vec<Vec<String>>> {
+    let mut commands = vec![];
+    let mut current: Option<Vec<String>> = None;
+    let mut depth = 0;
+    let mut chars = data.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            ';' => {
+                // Comment until the end of the line
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            }
+            '(' => {
+                if current.is_none() {
+                    current = Some(vec![]);
+                }
+                depth += 1;
+            }
+            ')' => {
+                if depth == 0 {
+                    return Err(invalid_data("Unbalanced parenthesis"));
+                }
+                depth -= 1;
+                if depth == 0 {
+                    commands.extend(current.take());
+                }
+            }
+            c if c.is_whitespace() => {}
+            c => {
+                let mut atom = c.to_string();
+                if c == '"' {
+                    for c in chars.by_ref() {
+                        atom.push(c);
+                        if c == '"' {
+                            break;
+                        }
+                    }
+                } else {
+                    while let Some(&c) = chars.peek() {
+                        if c.is_whitespace() || c == '(' || c == ')' {
+                            break;
+                        }
+                        atom.push(c);
+                        chars.next();
+                    }
+                }
+                current
+                    .as_mut()
+                    .ok_or_else(|| invalid_data(format!("Unexpected {}", atom)))?
+                    .push(atom);
+            }
+        }
+    }
+    if current.is_some() {
+        return Err(invalid_data("Unbalanced parenthesis"));
+    }
+    Ok(commands)
+}
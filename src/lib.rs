@@ -1 +1,62 @@
+//! Mandelbrot renderer with a CPU and an OpenCL backend.
+//!
+//! [`FractalRenderer`] renders views into `image` buffers:
+//!
+//! ```
+//! use brot_rs::{Backend, FractalRenderer};
+//!
+//! let mut renderer = FractalRenderer::builder()
+//!     .backend(Backend::Cpu)
+//!     .size(64, 48)
+//!     .center(-0.75, 0.1)
+//!     .zoom(4.0)
+//!     .iterations(200.0)
+//!     .build()?;
+//! let image = renderer.render()?;
+//! assert_eq!(image.dimensions(), (64, 48));
+//! # Ok::<(), String>(())
+//! ```
+//!
+//! [`RendererBuilder::spawn`] renders in the background. The task yields the tiles as they
+//! finish and is a future of the image:
+//!
+//! ```
+//! use brot_rs::{Backend, FractalRenderer, RenderEvent};
+//!
+//! let mut task = FractalRenderer::builder()
+//!     .backend(Backend::Cpu)
+//!     .size(64, 48)
+//!     .spawn()?;
+//! for event in task.by_ref() {
+//!     if let RenderEvent::Tile(tile) = event {
+//!         println!("{}x{} at {}, {}", tile.width, tile.height, tile.x, tile.y);
+//!     }
+//! }
+//! let image = task.wait()?;
+//! # Ok::<(), String>(())
+//! ```
+//!
+//! Interactive front ends can use the [`renderer::renderer_thread`] the GUI runs on, the other
+//! modules hold the exporters behind the command line tool.
+
 pub mod algorithms;
+pub mod animation;
+pub mod batch;
+pub mod cycle;
+pub mod distributed;
+pub mod export;
+pub mod fractal;
+pub mod history;
+pub mod import;
+pub mod location;
+pub mod overlay;
+pub mod pyramid;
+pub mod renderer;
+pub mod reproject;
+pub mod server;
+pub mod tiled;
+pub mod timeline;
+pub mod video;
+
+pub use algorithms::mandelbrot::{Float, FractalProperties};
+pub use fractal::{Backend, FractalRenderer, RenderEvent, RenderTask, RendererBuilder, Tile};
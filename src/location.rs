@@ -0,0 +1,86 @@
+use std::{fs, io, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::algorithms::mandelbrot::FractalProperties;
+
+/// Version written into new location files, files with a newer version are rejected
+pub const LOCATION_VERSION: u32 = 1;
+
+/// Iterated formula of the fractal
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum Formula {
+    /// `z = z^2 + c`
+    Mandelbrot,
+}
+
+/// Palette used to turn iteration counts into colours
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum Palette {
+    /// Hue cycles with the iteration count by `color_offset` degrees per iteration,
+    /// with a fixed `color_saturation`
+    HueCycle,
+}
+
+/// A saved view: everything needed to render the exact same image again.
+///
+/// Stored as JSON, coordinates are written with as many digits as needed to read back the
+/// exact same `f64`.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct Location {
+    pub version: u32,
+    pub formula: Formula,
+    pub palette: Palette,
+    pub properties: FractalProperties,
+}
+
+impl Location {
+    pub fn new(properties: FractalProperties) -> Self {
+        Self {
+            version: LOCATION_VERSION,
+            formula: Formula::Mandelbrot,
+            palette: Palette::HueCycle,
+            properties,
+        }
+    }
+
+    /// Parse a location, rejecting files written by a newer version of the format and views
+    /// that [`FractalProperties::validate`] rejects.
+    pub fn parse(data: &str) -> io::Result<Self> {
+        let location: Location = serde_json::from_str(data)?;
+        if location.version > LOCATION_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Location format version {} is newer than the supported version {}",
+                    location.version, LOCATION_VERSION
+                ),
+            ));
+        }
+        location
+            .properties
+            .validate()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        Ok(location)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("Failed serializing location")
+    }
+
+    pub fn read(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn write(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, self.to_json())
+    }
+}
+
+impl From<FractalProperties> for Location {
+    fn from(properties: FractalProperties) -> Self {
+        Self::new(properties)
+    }
+}
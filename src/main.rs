@@ -1,8 +1,6 @@
 pub use egui;
 
-mod algorithms;
 mod gui;
-mod renderer;
 
 fn main() {
     gui::run_gui();
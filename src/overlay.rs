@@ -0,0 +1,179 @@
+use std::{fs, io, path::PathBuf, str::FromStr};
+
+use fontdue::{
+    layout::{CoordinateSystem, Layout, LayoutSettings, TextStyle},
+    Font, FontSettings,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::algorithms::mandelbrot::{Float, FractalProperties};
+
+/// Corner of the image the overlay is placed in
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Anchor {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl FromStr for Anchor {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().replace('_', "-").as_str() {
+            "top-left" => Ok(Anchor::TopLeft),
+            "top-right" => Ok(Anchor::TopRight),
+            "bottom-left" => Ok(Anchor::BottomLeft),
+            "bottom-right" => Ok(Anchor::BottomRight),
+            _ => Err(format!(
+                "Unknown position {}, use top-left, top-right, bottom-left or bottom-right",
+                s
+            )),
+        }
+    }
+}
+
+/// Text drawn over rendered frames and images. The template is written as is, except for the
+/// placeholders:
+///
+/// - `{zoom}`: zoom in scientific notation
+/// - `{iterations}`: maximum number of iterations
+/// - `{center}`: center of the view, with enough digits to tell views at this zoom apart
+/// - `{rotation}`: rotation of the view in degrees
+/// - `{time}`: time of a video frame as `mm:ss.ss`, empty for still images
+/// - `{frame}`: number of a video frame, empty for still images
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct Overlay {
+    pub template: String,
+    pub anchor: Anchor,
+    /// Distance of the text from the anchored corner in pixels
+    pub margin: [u32; 2],
+    /// Font size in pixels
+    pub size: f32,
+    /// RGBA, the alpha fades the text into the image
+    pub color: [u8; 4],
+    /// TrueType or OpenType font, the GUI falls back to its own font
+    pub font: Option<PathBuf>,
+}
+
+impl Default for Overlay {
+    fn default() -> Self {
+        Self {
+            template: "{zoom}".to_string(),
+            anchor: Anchor::TopLeft,
+            margin: [30, 30],
+            size: 50.0,
+            color: [255, 255, 255, 255],
+            font: None,
+        }
+    }
+}
+
+impl Overlay {
+    /// The template with its placeholders filled in. `frame` and `time` are only known for
+    /// video frames.
+    pub fn text(&self, fp: &FractalProperties, frame: Option<u32>, time: Option<Float>) -> String {
+        // Enough digits to move the center by a hundredth of the view
+        let digits = (fp.zoom.log10().ceil() + 2.0).max(2.0) as usize;
+        self.template
+            .replace("{zoom}", &format!("{:.3e}", fp.zoom))
+            .replace("{iterations}", &format!("{}", fp.max_iter.round()))
+            .replace(
+                "{center}",
+                &format!("{:.*}, {:.*}", digits, fp.center_x, digits, fp.center_y),
+            )
+            .replace("{rotation}", &format!("{:.1}°", fp.rotation))
+            .replace(
+                "{time}",
+                &time
+                    .map(|t| format!("{:02}:{:05.2}", (t / 60.0).floor(), t % 60.0))
+                    .unwrap_or_default(),
+            )
+            .replace("{frame}", &frame.map(|f| f.to_string()).unwrap_or_default())
+    }
+
+    /// Load the font of the overlay, or `default_font` if it doesn't name one
+    pub fn load_font(&self, default_font: Option<&[u8]>) -> io::Result<Font> {
+        let data = match (&self.font, default_font) {
+            (Some(path), _) => fs::read(path)?,
+            (None, Some(data)) => data.to_vec(),
+            (None, None) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "The overlay needs a font file",
+                ))
+            }
+        };
+        Font::from_bytes(data, FontSettings::default())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Draw `text` onto the image, blending the anti-aliased glyphs by their coverage and the
+    /// alpha of the colour. Text running off the image is cut off.
+    pub fn draw(&self, font: &Font, text: &str, width: u32, height: u32, pixels: &mut [[u8; 3]]) {
+        let mut layout = Layout::new(CoordinateSystem::PositiveYDown);
+        layout.reset(&LayoutSettings::default());
+        layout.append(&[font], &TextStyle::new(text, self.size, 0));
+
+        let text_width = layout
+            .glyphs()
+            .iter()
+            .map(|g| g.x + g.width as f32)
+            .fold(0.0, f32::max);
+        let [margin_x, margin_y] = self.margin.map(|m| m as f32);
+        let x = match self.anchor {
+            Anchor::TopLeft | Anchor::BottomLeft => margin_x,
+            Anchor::TopRight | Anchor::BottomRight => width as f32 - margin_x - text_width,
+        };
+        let y = match self.anchor {
+            Anchor::TopLeft | Anchor::TopRight => margin_y,
+            Anchor::BottomLeft | Anchor::BottomRight => height as f32 - margin_y - layout.height(),
+        };
+
+        let [r, g, b, a] = self.color;
+        for glyph in layout.glyphs() {
+            if !glyph.char_data.rasterize() {
+                continue;
+            }
+            let (metrics, coverage) = font.rasterize_config(glyph.key);
+            let left = (x + glyph.x).round() as i64;
+            let top = (y + glyph.y).round() as i64;
+            for (i, c) in coverage.iter().enumerate() {
+                let px = left + (i % metrics.width) as i64;
+                let py = top + (i / metrics.width) as i64;
+                if *c == 0 || px < 0 || py < 0 || px >= width as i64 || py >= height as i64 {
+                    continue;
+                }
+                let alpha = *c as Float / 255.0 * a as Float / 255.0;
+                let pixel = &mut pixels[py as usize * width as usize + px as usize];
+                *pixel = blend(*pixel, [r, g, b], alpha);
+            }
+        }
+    }
+}
+
+/// Mix `color` over `pixel`, `alpha` between 0 and 1
+pub fn blend(pixel: [u8; 3], color: [u8; 3], alpha: Float) -> [u8; 3] {
+    let mut result = pixel;
+    for (p, c) in result.iter_mut().zip(color) {
+        *p = (*p as Float + (c as Float - *p as Float) * alpha).round() as u8;
+    }
+    result
+}
+
+/// Parse a `RRGGBB` or `RRGGBBAA` hex colour, with or without a leading `#`
+pub fn parse_color(s: &str) -> Result<[u8; 4], String> {
+    let hex = s.trim_start_matches('#');
+    let invalid = || format!("Invalid colour {}, use RRGGBB or RRGGBBAA", s);
+    if !(hex.len() == 6 || hex.len() == 8) || !hex.is_ascii() {
+        return Err(invalid());
+    }
+    let mut color = [255; 4];
+    for (i, c) in color.iter_mut().enumerate().take(hex.len() / 2) {
+        *c = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).map_err(|_| invalid())?;
+    }
+    Ok(color)
+}
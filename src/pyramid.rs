@@ -0,0 +1,248 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use image::ColorType;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    algorithms::mandelbrot::{AlgorithmType, Float, FractalProperties},
+    batch::Renderer,
+    tiled::scaled_tile_properties,
+};
+
+/// How the tiles of a pyramid are laid out on disk
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PyramidLayout {
+    /// `<name>.dzi` describing the image and tiles in `<name>_files/<level>/<col>_<row>.png`,
+    /// level 0 being a single pixel and the last level the full image
+    DeepZoom,
+    /// `<z>/<x>/<y>.png` in a directory, zoom level `z` splitting the square spanned by the
+    /// view into 2^z by 2^z tiles
+    Xyz,
+}
+
+impl PyramidLayout {
+    /// Deep Zoom for `.dzi` files, XYZ for everything else
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension() {
+            Some(ext) if ext.eq_ignore_ascii_case("dzi") => PyramidLayout::DeepZoom,
+            _ => PyramidLayout::Xyz,
+        }
+    }
+}
+
+/// Multi-resolution tile pyramid of a view, for zoomable web viewers. Every level is rendered
+/// at its own resolution instead of downscaled from the one below, so zooming in reveals new
+/// detail.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct PyramidSettings {
+    /// `.dzi` file or XYZ directory, see [`PyramidLayout::from_path`]
+    pub output: PathBuf,
+    /// Size of the full resolution image, the XYZ layout uses the longer side
+    pub width: u32,
+    pub height: u32,
+    pub tile_size: u32,
+    /// Pixels Deep Zoom tiles share with their neighbours
+    pub overlap: u32,
+}
+
+impl Default for PyramidSettings {
+    fn default() -> Self {
+        Self {
+            output: PathBuf::from("pyramid.dzi"),
+            width: 8192,
+            height: 8192,
+            tile_size: 256,
+            overlap: 1,
+        }
+    }
+}
+
+/// Single tile of a pyramid, the `region` `(x, y, width, height)` of an image of `level_size`
+#[derive(Debug, Clone, PartialEq)]
+pub struct PyramidTile {
+    pub level: u32,
+    pub path: PathBuf,
+    pub level_size: (u32, u32),
+    pub region: (u32, u32, u32, u32),
+}
+
+impl PyramidSettings {
+    pub fn layout(&self) -> PyramidLayout {
+        PyramidLayout::from_path(&self.output)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.width == 0 || self.height == 0 || self.tile_size == 0 {
+            return Err("Image and tile size must not be empty".to_string());
+        }
+        if self.overlap >= self.tile_size {
+            return Err("The tile overlap must be smaller than the tiles".to_string());
+        }
+        Ok(())
+    }
+
+    /// Number of levels, Deep Zoom halves the image down to a single pixel, XYZ down to a
+    /// single tile
+    pub fn levels(&self) -> u32 {
+        let longest = self.width.max(self.height) as Float;
+        match self.layout() {
+            PyramidLayout::DeepZoom => longest.log2().ceil() as u32 + 1,
+            PyramidLayout::Xyz => {
+                (longest / self.tile_size as Float).log2().ceil().max(0.0) as u32 + 1
+            }
+        }
+    }
+
+    /// Pixels of the last level covered by a pixel of `level` along each axis
+    pub fn level_scale(&self, level: u32) -> Float {
+        (1u64 << (self.levels() - 1 - level)) as Float
+    }
+
+    /// Image size of a level
+    pub fn level_size(&self, level: u32) -> (u32, u32) {
+        match self.layout() {
+            PyramidLayout::DeepZoom => {
+                let scale = self.level_scale(level);
+                (
+                    (self.width as Float / scale).ceil() as u32,
+                    (self.height as Float / scale).ceil() as u32,
+                )
+            }
+            PyramidLayout::Xyz => {
+                let size = self.tile_size << level;
+                (size, size)
+            }
+        }
+    }
+
+    /// Directory the tiles are written to
+    pub fn tile_dir(&self) -> PathBuf {
+        match self.layout() {
+            PyramidLayout::DeepZoom => {
+                let mut name = self.output.file_stem().unwrap_or_default().to_owned();
+                name.push("_files");
+                self.output.with_file_name(name)
+            }
+            PyramidLayout::Xyz => self.output.clone(),
+        }
+    }
+
+    /// Every tile of every level, from the smallest level up
+    pub fn tiles(&self) -> Vec<PyramidTile> {
+        let dir = self.tile_dir();
+        let mut tiles = vec![];
+        for level in 0..self.levels() {
+            let (width, height) = self.level_size(level);
+            let (cols, rows) = (
+                (width + self.tile_size - 1) / self.tile_size,
+                (height + self.tile_size - 1) / self.tile_size,
+            );
+            for row in 0..rows {
+                for col in 0..cols {
+                    let (path, region) = match self.layout() {
+                        PyramidLayout::DeepZoom => (
+                            dir.join(level.to_string())
+                                .join(format!("{}_{}.png", col, row)),
+                            self.overlapping_region(col, row, width, height),
+                        ),
+                        PyramidLayout::Xyz => (
+                            dir.join(level.to_string())
+                                .join(col.to_string())
+                                .join(format!("{}.png", row)),
+                            (
+                                col * self.tile_size,
+                                row * self.tile_size,
+                                self.tile_size,
+                                self.tile_size,
+                            ),
+                        ),
+                    };
+                    tiles.push(PyramidTile {
+                        level,
+                        path,
+                        level_size: (width, height),
+                        region,
+                    });
+                }
+            }
+        }
+        tiles
+    }
+
+    /// Region of a Deep Zoom tile, grown by the overlap on every side that has a neighbour
+    fn overlapping_region(
+        &self,
+        col: u32,
+        row: u32,
+        width: u32,
+        height: u32,
+    ) -> (u32, u32, u32, u32) {
+        let x = (col * self.tile_size).saturating_sub(self.overlap);
+        let y = (row * self.tile_size).saturating_sub(self.overlap);
+        let right = ((col + 1) * self.tile_size + self.overlap).min(width);
+        let bottom = ((row + 1) * self.tile_size + self.overlap).min(height);
+        (x, y, right - x, bottom - y)
+    }
+
+    /// Contents of the `.dzi` descriptor
+    pub fn dzi(&self) -> String {
+        format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+             <Image xmlns=\"http://schemas.microsoft.com/deepzoom/2008\" Format=\"png\" \
+             Overlap=\"{}\" TileSize=\"{}\">\n  <Size Width=\"{}\" Height=\"{}\"/>\n</Image>\n",
+            self.overlap, self.tile_size, self.width, self.height
+        )
+    }
+}
+
+/// Render every tile of the pyramid and write the `.dzi` descriptor if there is one. `on_tile`
+/// is called with the number of written tiles and the total after every tile.
+pub fn export_pyramid<F>(
+    settings: &PyramidSettings,
+    fp: FractalProperties,
+    algorithm: &AlgorithmType,
+    renderer: &mut Renderer,
+    on_tile: F,
+) -> Result<(), String>
+where
+    F: Fn(usize, usize),
+{
+    settings.validate()?;
+    let start = Instant::now();
+    let error = |path: &Path, e| format!("Failed writing {}: {}", path.display(), e);
+
+    // Every level is the last one shrunk with the top left corner in place, not the view
+    // fitted into the rounded up size of the level, so that the levels line up
+    let (width, height) = settings.level_size(settings.levels() - 1);
+    let tiles = settings.tiles();
+    for (i, tile) in tiles.iter().enumerate() {
+        let scale = settings.level_scale(tile.level);
+        let tile_fp = scaled_tile_properties(fp, width, height, scale, tile.region);
+        let (_, _, w, h) = tile.region;
+        let pixels = renderer.render(algorithm, w, h, tile_fp)?;
+
+        if let Some(dir) = tile.path.parent() {
+            fs::create_dir_all(dir).map_err(|e| error(dir, e))?;
+        }
+        image::save_buffer(&tile.path, &pixels.concat(), w, h, ColorType::Rgb8)
+            .map_err(|e| format!("Failed writing {}: {}", tile.path.display(), e))?;
+        on_tile(i + 1, tiles.len());
+    }
+
+    if settings.layout() == PyramidLayout::DeepZoom {
+        fs::write(&settings.output, settings.dzi()).map_err(|e| error(&settings.output, e))?;
+    }
+    println!(
+        "Exported {} tiles in {} levels in: {}ms",
+        tiles.len(),
+        settings.levels(),
+        start.elapsed().as_millis()
+    );
+    Ok(())
+}
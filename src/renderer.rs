@@ -1,20 +1,46 @@
-use std::{thread, time::Instant};
-
-use crossbeam_channel::{unbounded, Receiver, Sender};
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
 
 use crate::algorithms::{
-    mandelbrot::{AlgorithmType, FractalProperties},
+    coloring::calculate_pixel_color,
+    mandelbrot::{total_iterations, AlgorithmType, Float, FractalProperties, ViewTransform},
     *,
 };
+use crossbeam_channel::{unbounded, Receiver, Sender};
+use rayon::prelude::*;
 
 #[cfg(feature = "opencl")]
 use crate::algorithms::opencl::OpenCLRenderer;
 
+/// Messages between an interactive front end and the [`renderer_thread`]. The front end sends
+/// `RenderCommand`s, the thread answers with tiles and progress while rendering and the whole
+/// image at the end, or an error if the backend failed. Tiles, progress and errors carry the id
+/// of the command they belong to, so that front ends can drop the ones of renders they no longer
+/// wait for.
 pub enum RendererMessage {
-    RenderCommand(u32, u32, AlgorithmType, FractalProperties),
+    /// `(id, width, height, algorithm, view)`
+    RenderCommand(u64, u32, u32, AlgorithmType, FractalProperties),
     RenderedImage(Vec<[u8; 3]>, u32, u32),
+    /// A finished region of the image being rendered: `(id, x, y, width, height, pixels)`
+    RenderedTile(u64, u32, u32, u32, u32, Vec<[u8; 3]>),
+    /// Progress of the image being rendered: `(id, completed pixels, total pixels, iterations)`
+    RenderProgress(u64, u64, u64, u64),
+    /// The render failed, no image follows: `(id, error)`
+    RenderFailed(u64, String),
 }
 
+/// Image, iteration counts and number of newly calculated iterations of a shifted render
+type ShiftedRender = (Vec<[u8; 3]>, Vec<Float>, u64);
+
+/// Largest fractional pixel offset that still counts as an integer shift
+const SHIFT_TOLERANCE: Float = 1e-3;
+
+/// Start a thread rendering every `RenderCommand` sent to it, returns the sender for the
+/// commands and the receiver for the results. Views that are the previous one panned by whole
+/// pixels only render the newly exposed pixels.
 pub fn renderer_thread() -> (Sender<RendererMessage>, Receiver<RendererMessage>) {
     let (s1, r1) = unbounded();
     let (s2, r2) = unbounded();
@@ -29,6 +55,7 @@ pub fn renderer_thread() -> (Sender<RendererMessage>, Receiver<RendererMessage>)
 struct RendererThread {
     #[cfg(feature = "opencl")]
     opencl_renderer: OpenCLRenderer,
+    last_render: Option<LastRender>,
 }
 
 impl Default for RendererThread {
@@ -36,10 +63,60 @@ impl Default for RendererThread {
         Self {
             #[cfg(feature = "opencl")]
             opencl_renderer: Default::default(),
+            last_render: None,
         }
     }
 }
 
+/// Iteration counts of the previous render, kept so that pans can reuse the already known pixels.
+struct LastRender {
+    algorithm: AlgorithmType,
+    width: u32,
+    height: u32,
+    fp: FractalProperties,
+    iterations: Vec<Float>,
+}
+
+impl LastRender {
+    /// Offset in pixels from this render to the requested view, if the requested view is
+    /// this one shifted by a whole number of pixels.
+    fn pixel_offset(
+        &self,
+        algorithm: &AlgorithmType,
+        width: u32,
+        height: u32,
+        fp: &FractalProperties,
+    ) -> Option<(i64, i64)> {
+        // Backends differ in precision, their pixels can't be mixed
+        if self.algorithm != *algorithm
+            || self.width != width
+            || self.height != height
+            || self.fp.zoom != fp.zoom
+            || self.fp.max_iter != fp.max_iter
+            || self.fp.ss_factor != fp.ss_factor
+            || self.fp.rotation != fp.rotation
+            || self.fp.skew != fp.skew
+            || self.fp.stretch != fp.stretch
+        {
+            return None;
+        }
+
+        // Where the requested center lies in the previous image
+        let transform = ViewTransform::new(&self.fp, width as Float, height as Float);
+        let (x, y) = transform.to_pixel(fp.center_x, fp.center_y);
+        let dx = x - width as Float / 2.0;
+        let dy = y - height as Float / 2.0;
+        if (dx - dx.round()).abs() > SHIFT_TOLERANCE
+            || (dy - dy.round()).abs() > SHIFT_TOLERANCE
+            || dx.abs() >= width as Float
+            || dy.abs() >= height as Float
+        {
+            return None;
+        }
+        Some((dx.round() as i64, dy.round() as i64))
+    }
+}
+
 impl RendererThread {
     fn renderer_loop(
         mut self,
@@ -48,25 +125,221 @@ impl RendererThread {
     ) {
         loop {
             for cmd in &renderer_receiver {
-                if let RendererMessage::RenderCommand(width, height, algorithm, fp) = cmd {
+                if let RendererMessage::RenderCommand(id, width, height, algorithm, fp) = cmd {
                     let start = Instant::now();
-                    let img = match algorithm {
-                        AlgorithmType::NaiveCPU => naive_cpu::generate_image(width, height, fp),
-                        #[cfg(feature = "opencl")]
-                        AlgorithmType::OpenCL => self
-                            .opencl_renderer
-                            .generate_image(width, height, fp)
-                            .unwrap(),
+                    let total_pixels = width as u64 * height as u64;
+                    let completed_pixels = AtomicU64::new(0);
+                    let total_iterations = AtomicU64::new(0);
+                    let send_tile = |x, y, w, h, pixels: &[[u8; 3]], iterations| {
+                        gui_sender
+                            .send(RendererMessage::RenderedTile(
+                                id,
+                                x,
+                                y,
+                                w,
+                                h,
+                                pixels.to_vec(),
+                            ))
+                            .unwrap();
+                        let completed = completed_pixels
+                            .fetch_add(w as u64 * h as u64, Ordering::Relaxed)
+                            + w as u64 * h as u64;
+                        let iterations =
+                            total_iterations.fetch_add(iterations, Ordering::Relaxed) + iterations;
+                        gui_sender
+                            .send(RendererMessage::RenderProgress(
+                                id,
+                                completed,
+                                total_pixels,
+                                iterations,
+                            ))
+                            .unwrap();
                     };
-                    gui_sender
-                        .send(RendererMessage::RenderedImage(img, width, height))
-                        .unwrap();
-                    println!(
-                        "Generated and sent image in: {}ms",
-                        start.elapsed().as_millis()
-                    );
+                    let offset = self
+                        .last_render
+                        .as_ref()
+                        .and_then(|last| last.pixel_offset(&algorithm, width, height, &fp));
+                    let result = match offset {
+                        Some(offset) => {
+                            let last = self.last_render.take().unwrap();
+                            self.render_shifted(&algorithm, last, offset, fp).map(
+                                |(img, iterations, new_iterations)| {
+                                    send_tile(0, 0, width, height, &img, new_iterations);
+                                    (img, iterations)
+                                },
+                            )
+                        }
+                        None => match algorithm {
+                            AlgorithmType::NaiveCPU => Ok(naive_cpu::generate_image_tiled(
+                                width, height, fp, send_tile,
+                            )),
+                            #[cfg(feature = "opencl")]
+                            AlgorithmType::OpenCL => self
+                                .opencl_renderer
+                                .generate_image_tiled(width, height, fp, send_tile),
+                        },
+                    };
+                    match result {
+                        Ok((img, iterations)) => {
+                            self.last_render = Some(LastRender {
+                                algorithm,
+                                width,
+                                height,
+                                fp,
+                                iterations,
+                            });
+                            gui_sender
+                                .send(RendererMessage::RenderedImage(img, width, height))
+                                .unwrap();
+                            println!(
+                                "Generated and sent image in: {}ms",
+                                start.elapsed().as_millis()
+                            );
+                        }
+                        Err(e) => {
+                            println!("Failed rendering image: {}", e);
+                            gui_sender
+                                .send(RendererMessage::RenderFailed(id, e))
+                                .unwrap();
+                        }
+                    }
                 }
             }
         }
     }
+
+    /// Produce the requested view by shifting the previous render by `(dx, dy)` pixels and
+    /// only calculating the newly exposed strips. Returns the image, its iteration counts and
+    /// the number of iterations that were actually calculated, or the error of the backend.
+    fn render_shifted(
+        &mut self,
+        algorithm: &AlgorithmType,
+        last: LastRender,
+        (dx, dy): (i64, i64),
+        fp: FractalProperties,
+    ) -> Result<ShiftedRender, String> {
+        let (width, height) = (last.width, last.height);
+        let (w, h) = (width as i64, height as i64);
+
+        // Columns of the new image that are still covered by the previous render
+        let kept_x = (-dx).max(0)..(w - dx).min(w);
+        let mut iterations = vec![0 as Float; (w * h) as usize];
+        for y in 0..h {
+            let src_y = y + dy;
+            if !(0..h).contains(&src_y) {
+                continue;
+            }
+            let dst = (y * w + kept_x.start) as usize..(y * w + kept_x.end) as usize;
+            let src =
+                (src_y * w + kept_x.start + dx) as usize..(src_y * w + kept_x.end + dx) as usize;
+            iterations[dst].copy_from_slice(&last.iterations[src]);
+        }
+
+        let mut exposed = vec![];
+        if dx > 0 {
+            exposed.push(((w - dx) as u32, 0, dx as u32, height));
+        } else if dx < 0 {
+            exposed.push((0, 0, (-dx) as u32, height));
+        }
+        if dy > 0 {
+            exposed.push((
+                kept_x.start as u32,
+                (h - dy) as u32,
+                (kept_x.end - kept_x.start) as u32,
+                dy as u32,
+            ));
+        } else if dy < 0 {
+            exposed.push((
+                kept_x.start as u32,
+                0,
+                (kept_x.end - kept_x.start) as u32,
+                (-dy) as u32,
+            ));
+        }
+
+        let mut new_iterations = 0;
+        for region in exposed {
+            let (x, y, rw, rh) = region;
+            let values = match algorithm {
+                AlgorithmType::NaiveCPU => naive_cpu::generate_region(region, width, height, fp),
+                #[cfg(feature = "opencl")]
+                AlgorithmType::OpenCL => self
+                    .opencl_renderer
+                    .generate_region(region, width, height, fp)?,
+            };
+            new_iterations += total_iterations(&values, fp);
+            for row in 0..rh {
+                let dst = ((y + row) * width + x) as usize;
+                iterations[dst..dst + rw as usize]
+                    .copy_from_slice(&values[(row * rw) as usize..((row + 1) * rw) as usize]);
+            }
+        }
+
+        let img = iterations
+            .par_iter()
+            .map(|n| calculate_pixel_color(fp, *n))
+            .collect();
+        Ok((img, iterations, new_iterations))
+    }
+}
+
+/// Progress of a render in flight, updated from the `RenderProgress` messages of the
+/// [`renderer_thread`]
+#[derive(Debug, Clone)]
+pub struct RenderProgress {
+    /// Id of the render command
+    pub id: u64,
+    pub fp: FractalProperties,
+    pub size: [u32; 2],
+    pub started: Instant,
+    pub completed_pixels: u64,
+    pub total_pixels: u64,
+    pub iterations: u64,
+}
+
+impl RenderProgress {
+    pub fn new(id: u64, fp: FractalProperties, size: [u32; 2]) -> Self {
+        Self {
+            id,
+            fp,
+            size,
+            started: Instant::now(),
+            completed_pixels: 0,
+            total_pixels: 0,
+            iterations: 0,
+        }
+    }
+
+    pub fn fraction(&self) -> f32 {
+        if self.total_pixels == 0 {
+            0.0
+        } else {
+            (self.completed_pixels as f32 / self.total_pixels as f32).min(1.0)
+        }
+    }
+
+    /// Summary of the throughput and the estimated time left
+    pub fn status(&self) -> String {
+        self.status_after(self.started.elapsed())
+    }
+
+    /// [`status`](Self::status) once `elapsed` passed since the render started
+    pub fn status_after(&self, elapsed: Duration) -> String {
+        let elapsed = elapsed.as_secs_f64();
+        let mpix_per_sec = self.completed_pixels as f64 / elapsed / 1e6;
+        let miter_per_sec = self.iterations as f64 / elapsed / 1e6;
+        let eta = if self.completed_pixels == 0 {
+            "ETA: -".to_string()
+        } else {
+            let remaining = self.total_pixels.saturating_sub(self.completed_pixels) as f64;
+            format!(
+                "ETA: {:.1}s",
+                remaining / self.completed_pixels as f64 * elapsed
+            )
+        };
+        format!(
+            "{:.2} Mpix/s, {:.1} Miter/s, {}",
+            mpix_per_sec, miter_per_sec, eta
+        )
+    }
 }
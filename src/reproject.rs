@@ -0,0 +1,118 @@
+use rayon::prelude::*;
+
+use crate::algorithms::mandelbrot::{Float, FractalProperties};
+
+/// Keyframes are rendered this many times larger than the video frames, so that frames are
+/// always downsampled from them, never upscaled.
+pub const KEYFRAME_SCALE: u32 = 2;
+
+/// Keyframes of a straight zoom video, one at every doubling of the zoom. Every frame lies
+/// between two keyframes and is reprojected from the one zoomed out further, which shows all of
+/// it at no less than the frame's resolution.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ZoomKeyframes {
+    /// Zoom of the first keyframe, the lower of the start and end zoom
+    pub base_zoom: Float,
+    pub count: u32,
+}
+
+impl ZoomKeyframes {
+    pub fn new(start_zoom: Float, end_zoom: Float) -> Self {
+        let (low, high) = (start_zoom.min(end_zoom), start_zoom.max(end_zoom));
+        Self {
+            base_zoom: low,
+            count: (high / low).log2().floor() as u32 + 1,
+        }
+    }
+
+    pub fn zoom(&self, index: u32) -> Float {
+        self.base_zoom * (2 as Float).powi(index as i32)
+    }
+
+    /// Index of the keyframe a frame at `zoom` is reprojected from
+    pub fn index_of(&self, zoom: Float) -> u32 {
+        let index = ((zoom / self.base_zoom).log2().floor().max(0.0) as u32).min(self.count - 1);
+        // Don't let rounding pick a keyframe that doesn't cover the whole frame
+        if index > 0 && self.zoom(index) > zoom {
+            index - 1
+        } else {
+            index
+        }
+    }
+
+    /// Size of the keyframes of a video with the given frame size
+    pub fn size(width: u32, height: u32) -> (u32, u32) {
+        (width * KEYFRAME_SCALE, height * KEYFRAME_SCALE)
+    }
+
+    /// View of a keyframe, `fp` gives everything but the zoom
+    pub fn properties(&self, fp: FractalProperties, index: u32) -> FractalProperties {
+        FractalProperties {
+            zoom: self.zoom(index),
+            ..fp
+        }
+    }
+}
+
+/// Rendered keyframe, sharing the center and orientation of the frames reprojected from it.
+pub struct Keyframe {
+    pub index: u32,
+    pub zoom: Float,
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<[u8; 3]>,
+}
+
+impl Keyframe {
+    /// Resample the part of the keyframe shown by a `width`x`height` frame at `zoom`. Every
+    /// frame pixel averages 2x2 bilinear samples spread over its footprint in the keyframe, so
+    /// the result stays sharp without aliasing while the footprint shrinks from two keyframe
+    /// pixels to one.
+    pub fn reproject(&self, width: u32, height: u32, zoom: Float) -> Vec<[u8; 3]> {
+        // Keyframe pixels per frame pixel, the shorter side spans the same part of the view
+        let scale =
+            self.width.min(self.height) as Float / width.min(height) as Float * self.zoom / zoom;
+        let center = (self.width as Float / 2.0, self.height as Float / 2.0);
+        let offsets = [(-0.25, -0.25), (0.25, -0.25), (-0.25, 0.25), (0.25, 0.25)];
+
+        let mut pixels = vec![[0u8; 3]; (width * height) as usize];
+        pixels
+            .par_chunks_mut(width as usize)
+            .enumerate()
+            .for_each(|(y, row)| {
+                let ky = center.1 + (y as Float + 0.5 - height as Float / 2.0) * scale;
+                for (x, pixel) in row.iter_mut().enumerate() {
+                    let kx = center.0 + (x as Float + 0.5 - width as Float / 2.0) * scale;
+                    let mut sum = [0 as Float; 3];
+                    for (dx, dy) in offsets {
+                        let sample = self.sample(kx + dx * scale, ky + dy * scale);
+                        for (s, c) in sum.iter_mut().zip(sample) {
+                            *s += c;
+                        }
+                    }
+                    *pixel = sum.map(|s| (s / offsets.len() as Float).round() as u8);
+                }
+            });
+        pixels
+    }
+
+    /// Bilinear sample at a position in pixels, clamped to the edges
+    fn sample(&self, x: Float, y: Float) -> [Float; 3] {
+        // Pixel centers are at half pixels
+        let x = (x - 0.5).clamp(0.0, (self.width - 1) as Float);
+        let y = (y - 0.5).clamp(0.0, (self.height - 1) as Float);
+        let (x0, y0) = (x.floor() as u32, y.floor() as u32);
+        let (x1, y1) = ((x0 + 1).min(self.width - 1), (y0 + 1).min(self.height - 1));
+        let (fx, fy) = (x - x0 as Float, y - y0 as Float);
+
+        let pixel = |x: u32, y: u32| self.pixels[(y * self.width + x) as usize];
+        let (p00, p10, p01, p11) = (pixel(x0, y0), pixel(x1, y0), pixel(x0, y1), pixel(x1, y1));
+        let mut result = [0 as Float; 3];
+        for (c, r) in result.iter_mut().enumerate() {
+            let top = p00[c] as Float + (p10[c] as Float - p00[c] as Float) * fx;
+            let bottom = p01[c] as Float + (p11[c] as Float - p01[c] as Float) * fx;
+            *r = top + (bottom - top) * fy;
+        }
+        result
+    }
+}
@@ -0,0 +1,353 @@
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{Ipv4Addr, TcpListener, TcpStream},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    algorithms::mandelbrot::{AlgorithmType, Float, FractalProperties},
+    batch::Renderer,
+    export,
+    location::Location,
+    tiled::tile_properties,
+};
+
+/// Connections sending their request slower than this are dropped
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Most bytes read of the request line and headers, longer requests are answered with 431
+const MAX_HEAD: u64 = 8 * 1024;
+
+/// Settings of the render server. Tiles follow the XYZ layout of the tile pyramids: zoom level
+/// `z` splits the square spanned by the view into 2^z by 2^z tiles.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerSettings {
+    pub port: u16,
+    pub tile_size: u32,
+    /// Number of tiles kept in the cache
+    pub cache_size: usize,
+    /// Most samples `/render` calculates for an image, the number of pixels times the
+    /// supersampling factor squared
+    pub max_samples: u64,
+}
+
+impl Default for ServerSettings {
+    fn default() -> Self {
+        Self {
+            port: 8080,
+            tile_size: 256,
+            cache_size: 512,
+            max_samples: 4096 * 4096,
+        }
+    }
+}
+
+/// `(z, x, y)` of an XYZ tile
+pub type TileKey = (u32, u32, u32);
+
+/// Encoded tiles by `(z, x, y)`, dropping the least recently used one when full
+pub struct TileCache {
+    capacity: usize,
+    /// Incremented on every access, tiles remember when they were last used
+    clock: u64,
+    tiles: HashMap<TileKey, (u64, Arc<Vec<u8>>)>,
+}
+
+impl TileCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            clock: 0,
+            tiles: HashMap::new(),
+        }
+    }
+
+    pub fn get(&mut self, key: TileKey) -> Option<Arc<Vec<u8>>> {
+        self.clock += 1;
+        let clock = self.clock;
+        self.tiles.get_mut(&key).map(|(used, tile)| {
+            *used = clock;
+            tile.clone()
+        })
+    }
+
+    pub fn insert(&mut self, key: TileKey, tile: Arc<Vec<u8>>) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.tiles.len() >= self.capacity && !self.tiles.contains_key(&key) {
+            let oldest = self
+                .tiles
+                .iter()
+                .min_by_key(|(_, (used, _))| *used)
+                .map(|(key, _)| *key);
+            if let Some(oldest) = oldest {
+                self.tiles.remove(&oldest);
+            }
+        }
+        self.clock += 1;
+        self.tiles.insert(key, (self.clock, tile));
+    }
+
+    pub fn len(&self) -> usize {
+        self.tiles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tiles.is_empty()
+    }
+}
+
+struct Response {
+    status: &'static str,
+    content_type: &'static str,
+    body: Arc<Vec<u8>>,
+    cached: bool,
+}
+
+impl Response {
+    fn png(body: Arc<Vec<u8>>, cached: bool) -> Self {
+        Self {
+            status: "200 OK",
+            content_type: "image/png",
+            body,
+            cached,
+        }
+    }
+
+    fn error(status: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            content_type: "text/plain; charset=utf-8",
+            body: Arc::new(message.into().into_bytes()),
+            cached: false,
+        }
+    }
+}
+
+/// Bind the server to `port` on localhost, port 0 picks a free one
+pub fn bind(port: u16) -> io::Result<TcpListener> {
+    TcpListener::bind((Ipv4Addr::LOCALHOST, port))
+}
+
+/// Answer requests one after the other until the listener fails, rendering with `renderer`.
+/// `fp` is the view the tiles are cut from, and the defaults of `/render`:
+///
+/// - `GET /render?cx=..&cy=..&zoom=..&w=..&h=..` renders a view as PNG, `iter`, `ss` and
+///   `rotation` can be given as well
+/// - `GET /tiles/{z}/{x}/{y}.png` renders an XYZ tile of the view
+pub fn serve(
+    listener: TcpListener,
+    settings: &ServerSettings,
+    fp: FractalProperties,
+    algorithm: &AlgorithmType,
+    renderer: &mut Renderer,
+) -> io::Result<()> {
+    println!("Serving on http://{}", listener.local_addr()?);
+    let mut cache = TileCache::new(settings.cache_size);
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let result = handle(stream, settings, fp, algorithm, renderer, &mut cache);
+        if let Err(e) = result {
+            eprintln!("Failed answering request: {}", e);
+        }
+    }
+    Ok(())
+}
+
+fn handle(
+    mut stream: TcpStream,
+    settings: &ServerSettings,
+    fp: FractalProperties,
+    algorithm: &AlgorithmType,
+    renderer: &mut Renderer,
+    cache: &mut TileCache,
+) -> io::Result<()> {
+    let start = Instant::now();
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let mut reader = BufReader::new((&stream).take(MAX_HEAD));
+    let head = read_request_line(&mut reader);
+    let head_too_long = reader.get_ref().limit() == 0;
+
+    let request_line = match head {
+        Ok(request_line) => request_line,
+        // Not UTF-8, answered as a malformed request
+        Err(e) if e.kind() == io::ErrorKind::InvalidData => String::new(),
+        Err(e) => return Err(e),
+    };
+    let mut parts = request_line.split_whitespace();
+    let (method, target) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+    let response = if head_too_long {
+        Response::error(
+            "431 Request Header Fields Too Large",
+            format!(
+                "Request line and headers must be shorter than {} bytes",
+                MAX_HEAD
+            ),
+        )
+    } else if method.is_empty() || target.is_empty() {
+        Response::error("400 Bad Request", "Malformed request line")
+    } else if method != "GET" {
+        Response::error("405 Method Not Allowed", "Only GET is supported")
+    } else {
+        route(target, settings, fp, algorithm, renderer, cache)
+    };
+
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nX-Cache: {}\r\n\
+         Connection: close\r\n\r\n",
+        response.status,
+        response.content_type,
+        response.body.len(),
+        if response.cached { "hit" } else { "miss" }
+    )?;
+    stream.write_all(&response.body)?;
+    stream.flush()?;
+    println!(
+        "{} {} {} in: {}ms{}",
+        method,
+        target,
+        response.status,
+        start.elapsed().as_millis(),
+        if response.cached { " (cached)" } else { "" }
+    );
+    Ok(())
+}
+
+/// Read the request line and skip the headers, nothing in them is needed
+fn read_request_line(reader: &mut impl BufRead) -> io::Result<String> {
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 0 && !header.trim_end().is_empty() {
+        header.clear();
+    }
+    Ok(request_line)
+}
+
+fn route(
+    target: &str,
+    settings: &ServerSettings,
+    fp: FractalProperties,
+    algorithm: &AlgorithmType,
+    renderer: &mut Renderer,
+    cache: &mut TileCache,
+) -> Response {
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    if path == "/render" {
+        return match render_view(query, settings, fp, algorithm, renderer) {
+            Ok(png) => Response::png(Arc::new(png), false),
+            Err(e) => Response::error("400 Bad Request", e),
+        };
+    }
+    if let Some(tile) = path.strip_prefix("/tiles/") {
+        let key = match parse_tile(tile, settings.tile_size) {
+            Some(key) => key,
+            None => return Response::error("404 Not Found", "No such tile"),
+        };
+        if let Some(png) = cache.get(key) {
+            return Response::png(png, true);
+        }
+        return match render_tile(key, settings, fp, algorithm, renderer) {
+            Ok(png) => {
+                let png = Arc::new(png);
+                cache.insert(key, png.clone());
+                Response::png(png, false)
+            }
+            Err(e) => Response::error("500 Internal Server Error", e),
+        };
+    }
+    Response::error("404 Not Found", "Use /render or /tiles/{z}/{x}/{y}.png")
+}
+
+/// `(z, x, y)` of a `{z}/{x}/{y}.png` tile path, if the tile exists
+fn parse_tile(path: &str, tile_size: u32) -> Option<TileKey> {
+    let mut parts = path.strip_suffix(".png")?.split('/');
+    let z: u32 = parts.next()?.parse().ok()?;
+    let x: u32 = parts.next()?.parse().ok()?;
+    let y: u32 = parts.next()?.parse().ok()?;
+    // The size of the level has to fit the renderer, checked before shifting by `z` from the URL
+    if parts.next().is_some() || z >= 32 || (tile_size as u64) << z > u32::MAX as u64 {
+        return None;
+    }
+    if (x as u64) < 1 << z && (y as u64) < 1 << z {
+        Some((z, x, y))
+    } else {
+        None
+    }
+}
+
+fn encode_png(
+    width: u32,
+    height: u32,
+    pixels: &[[u8; 3]],
+    fp: FractalProperties,
+) -> io::Result<Vec<u8>> {
+    let mut png = vec![];
+    let encoder = export::png_encoder(&mut png, width, height, &Location::new(fp))?;
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&pixels.concat())?;
+    writer.finish()?;
+    Ok(png)
+}
+
+fn render_tile(
+    (z, x, y): TileKey,
+    settings: &ServerSettings,
+    fp: FractalProperties,
+    algorithm: &AlgorithmType,
+    renderer: &mut Renderer,
+) -> Result<Vec<u8>, String> {
+    let (size, level) = (settings.tile_size, settings.tile_size << z);
+    let tile_fp = tile_properties(fp, level, level, (x * size, y * size, size, size));
+    let pixels = renderer.render(algorithm, size, size, tile_fp)?;
+    encode_png(size, size, &pixels, tile_fp).map_err(|e| e.to_string())
+}
+
+fn render_view(
+    query: &str,
+    settings: &ServerSettings,
+    fp: FractalProperties,
+    algorithm: &AlgorithmType,
+    renderer: &mut Renderer,
+) -> Result<Vec<u8>, String> {
+    let mut fp = fp;
+    let (mut width, mut height) = (800u32, 600u32);
+    for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+        let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+        let float = || {
+            value
+                .parse::<Float>()
+                .map_err(|_| format!("Invalid value for {}: {}", name, value))
+        };
+        let size = || {
+            value
+                .parse::<u32>()
+                .map_err(|_| format!("Invalid value for {}: {}", name, value))
+        };
+        match name {
+            "cx" => fp.center_x = float()?,
+            "cy" => fp.center_y = float()?,
+            "zoom" => fp.zoom = float()?,
+            "iter" => fp.max_iter = float()?,
+            "rotation" => fp.rotation = float()?,
+            "ss" => fp.ss_factor = size()? as i32,
+            "w" => width = size()?,
+            "h" => height = size()?,
+            _ => return Err(format!("Unknown parameter: {}", name)),
+        }
+    }
+    fp.validate()?;
+    let samples = width as u64 * height as u64 * (fp.ss_factor as u64).pow(2);
+    if width == 0 || height == 0 || samples > settings.max_samples {
+        return Err(format!(
+            "Image size times the supersampling factor squared must be between 1 and {}",
+            settings.max_samples
+        ));
+    }
+    let pixels = renderer.render(algorithm, width, height, fp)?;
+    encode_png(width, height, &pixels, fp).map_err(|e| e.to_string())
+}
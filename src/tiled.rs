@@ -0,0 +1,280 @@
+use std::{
+    fmt::Display,
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use serde::{Deserialize, Serialize};
+use tiff::encoder::{colortype::RGB8, TiffEncoder, TiffKind, TiffKindBig, TiffKindStandard};
+
+use crate::{
+    algorithms::mandelbrot::{AlgorithmType, Float, FractalProperties, ViewTransform},
+    batch::{Renderer, View},
+    export,
+    location::Location,
+};
+
+/// Largest TIFF written with 32 bit offsets, bigger images are written as BigTIFF. Leaves
+/// room below 4 GiB for the header and the strip tables.
+const STANDARD_TIFF_LIMIT: u64 = 4_000_000_000;
+
+/// Image too large to render in one go, rendered tile by tile and streamed to disk in bands of
+/// rows. Only one band is held in memory, `width * tile_size` pixels, however tall the image.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct TiledSettings {
+    /// PNG or TIFF to write, picked by the extension
+    pub output: PathBuf,
+    pub width: u32,
+    pub height: u32,
+    /// Side length of the tiles rendered at once, also the height of the bands
+    pub tile_size: u32,
+}
+
+impl Default for TiledSettings {
+    fn default() -> Self {
+        Self {
+            output: PathBuf::from("poster.png"),
+            width: 16384,
+            height: 16384,
+            tile_size: 1024,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TiledFormat {
+    Png,
+    Tiff,
+}
+
+impl TiledFormat {
+    fn from_path(path: &Path) -> Option<Self> {
+        let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+        match ext.as_str() {
+            "png" => Some(TiledFormat::Png),
+            "tif" | "tiff" => Some(TiledFormat::Tiff),
+            _ => None,
+        }
+    }
+}
+
+impl TiledSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.width == 0 || self.height == 0 || self.tile_size == 0 {
+            return Err("Image and tile size must not be empty".to_string());
+        }
+        if TiledFormat::from_path(&self.output).is_none() {
+            return Err(format!(
+                "Unsupported tiled image format {}, use .png or .tiff",
+                self.output.display()
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn bands(&self) -> u32 {
+        (self.height + self.tile_size - 1) / self.tile_size
+    }
+
+    /// `(x, y, width, height)` of the tiles of the band starting at row `y`, left to right
+    pub fn tiles(&self, y: u32) -> Vec<(u32, u32, u32, u32)> {
+        let h = self.tile_size.min(self.height - y);
+        (0..self.width)
+            .step_by(self.tile_size as usize)
+            .map(|x| (x, y, self.tile_size.min(self.width - x), h))
+            .collect()
+    }
+}
+
+/// View that renders the `(x, y, w, h)` region of a `width`x`height` image at `fp` as an image
+/// of its own. The scale and orientation stay the same, the center moves to the tile's.
+pub fn tile_properties(
+    fp: FractalProperties,
+    width: u32,
+    height: u32,
+    region: (u32, u32, u32, u32),
+) -> FractalProperties {
+    scaled_tile_properties(fp, width, height, 1.0, region)
+}
+
+/// Like [`tile_properties`], for the `width`x`height` image shrunk by `scale` with its top left
+/// corner kept in place. The region is in pixels of the shrunk image, each of them covering
+/// `scale` by `scale` pixels of the full one.
+pub fn scaled_tile_properties(
+    fp: FractalProperties,
+    width: u32,
+    height: u32,
+    scale: Float,
+    (x, y, w, h): (u32, u32, u32, u32),
+) -> FractalProperties {
+    let transform = ViewTransform::new(&fp, width as Float, height as Float);
+    let (center_x, center_y) = transform.to_complex(
+        (x as Float + w as Float / 2.0) * scale,
+        (y as Float + h as Float / 2.0) * scale,
+    );
+    FractalProperties {
+        center_x,
+        center_y,
+        // The shorter side spans 2 / zoom, scale the size of a pixel
+        zoom: fp.zoom * width.min(height) as Float / (scale * w.min(h) as Float),
+        ..fp
+    }
+}
+
+/// Views rendering the tiles of the band starting at row `y`, left to right
+pub fn band_views(settings: &TiledSettings, fp: FractalProperties, y: u32) -> Vec<View> {
+    settings
+        .tiles(y)
+        .into_iter()
+        .map(|tile| {
+            let (_, _, w, h) = tile;
+            (
+                w,
+                h,
+                tile_properties(fp, settings.width, settings.height, tile),
+            )
+        })
+        .collect()
+}
+
+/// Copy the rendered tiles of the band starting at row `y` into one image
+pub fn assemble_band(settings: &TiledSettings, y: u32, tiles: &[Vec<[u8; 3]>]) -> Vec<[u8; 3]> {
+    let band_height = settings.tile_size.min(settings.height - y);
+    let mut band = vec![[0u8; 3]; settings.width as usize * band_height as usize];
+    for ((x, _, w, _), pixels) in settings.tiles(y).into_iter().zip(tiles) {
+        for (row, src) in pixels.chunks(w as usize).enumerate() {
+            let start = row * settings.width as usize + x as usize;
+            band[start..start + w as usize].copy_from_slice(src);
+        }
+    }
+    band
+}
+
+/// Render the band of rows starting at `y` one tile after the other
+pub fn render_band(
+    settings: &TiledSettings,
+    fp: FractalProperties,
+    algorithm: &AlgorithmType,
+    renderer: &mut Renderer,
+    y: u32,
+) -> Result<Vec<[u8; 3]>, String> {
+    let tiles = renderer.render_views(algorithm, &band_views(settings, fp, y))?;
+    Ok(assemble_band(settings, y, &tiles))
+}
+
+fn write_error(path: &Path, e: impl Display) -> String {
+    format!("Failed writing {}: {}", path.display(), e)
+}
+
+/// Render the image band by band, writing every band before the next one is rendered.
+/// `on_band` is called with the index of every written band.
+pub fn render_tiled<F>(
+    settings: &TiledSettings,
+    fp: FractalProperties,
+    algorithm: &AlgorithmType,
+    renderer: &mut Renderer,
+    on_band: F,
+) -> Result<(), String>
+where
+    F: Fn(u32),
+{
+    write_tiled(settings, fp, |y| {
+        let band = render_band(settings, fp, algorithm, renderer, y)?;
+        on_band(y / settings.tile_size);
+        Ok(band)
+    })
+}
+
+/// Write the image with the bands `next_band` returns for the first row of every band, in
+/// order from the top.
+pub fn write_tiled<B>(
+    settings: &TiledSettings,
+    fp: FractalProperties,
+    mut next_band: B,
+) -> Result<(), String>
+where
+    B: FnMut(u32) -> Result<Vec<[u8; 3]>, String>,
+{
+    settings.validate()?;
+    let start = Instant::now();
+    let path = &settings.output;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| write_error(path, e))?;
+    }
+
+    // Written next to the output and renamed once complete, like every other image
+    let partial = export::partial_path(path);
+    let file = BufWriter::new(File::create(&partial).map_err(|e| write_error(path, e))?);
+    let size = settings.width as u64 * settings.height as u64 * 3;
+    match TiledFormat::from_path(path).unwrap() {
+        TiledFormat::Png => write_png(file, settings, fp, &mut next_band),
+        TiledFormat::Tiff if size > STANDARD_TIFF_LIMIT => {
+            write_tiff::<TiffKindBig, _>(file, settings, &mut next_band)
+        }
+        TiledFormat::Tiff => write_tiff::<TiffKindStandard, _>(file, settings, &mut next_band),
+    }?;
+    fs::rename(&partial, path).map_err(|e| write_error(path, e))?;
+
+    println!(
+        "Rendered {}x{} in {} bands in: {}ms",
+        settings.width,
+        settings.height,
+        settings.bands(),
+        start.elapsed().as_millis()
+    );
+    Ok(())
+}
+
+fn write_png<F>(
+    file: BufWriter<File>,
+    settings: &TiledSettings,
+    fp: FractalProperties,
+    next_band: &mut F,
+) -> Result<(), String>
+where
+    F: FnMut(u32) -> Result<Vec<[u8; 3]>, String>,
+{
+    let path = &settings.output;
+    let encoder = export::png_encoder(file, settings.width, settings.height, &Location::new(fp))
+        .map_err(|e| write_error(path, e))?;
+    let mut writer = encoder.write_header().map_err(|e| write_error(path, e))?;
+    let mut stream = writer.stream_writer().map_err(|e| write_error(path, e))?;
+    for y in (0..settings.height).step_by(settings.tile_size as usize) {
+        let band = next_band(y)?;
+        stream
+            .write_all(&band.concat())
+            .map_err(|e| write_error(path, e))?;
+    }
+    stream.finish().map_err(|e| write_error(path, e))?;
+    writer.finish().map_err(|e| write_error(path, e))
+}
+
+fn write_tiff<K, F>(
+    file: BufWriter<File>,
+    settings: &TiledSettings,
+    next_band: &mut F,
+) -> Result<(), String>
+where
+    K: TiffKind,
+    F: FnMut(u32) -> Result<Vec<[u8; 3]>, String>,
+{
+    let path = &settings.output;
+    let mut encoder = TiffEncoder::<_, K>::new_generic(file).map_err(|e| write_error(path, e))?;
+    let mut image = encoder
+        .new_image::<RGB8>(settings.width, settings.height)
+        .map_err(|e| write_error(path, e))?;
+    // Every band becomes one strip
+    image
+        .rows_per_strip(settings.tile_size)
+        .map_err(|e| write_error(path, e))?;
+    for y in (0..settings.height).step_by(settings.tile_size as usize) {
+        let band = next_band(y)?;
+        image
+            .write_strip(&band.concat())
+            .map_err(|e| write_error(path, e))?;
+    }
+    image.finish().map_err(|e| write_error(path, e))
+}
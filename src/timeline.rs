@@ -0,0 +1,270 @@
+use std::{cmp::Ordering, fs, io, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::algorithms::mandelbrot::{Float, FractalProperties};
+
+/// How a value moves from its key to the next one
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Interpolation {
+    Linear,
+    /// Eases in and out of the key
+    Smoothstep,
+    /// Smooth curve through the neighbouring keys as well
+    CatmullRom,
+    /// Constant zoom speed. Zooms change geometrically and centers move at a constant speed
+    /// relative to the zooming view, other values change geometrically.
+    ExponentialZoom,
+}
+
+/// `FractalProperties` fields that can be animated
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Property {
+    CenterX,
+    CenterY,
+    Zoom,
+    MaxIter,
+    ColorOffset,
+    ColorSaturation,
+    Rotation,
+    Skew,
+    Stretch,
+}
+
+impl Property {
+    pub const ALL: [Property; 9] = [
+        Property::CenterX,
+        Property::CenterY,
+        Property::Zoom,
+        Property::MaxIter,
+        Property::ColorOffset,
+        Property::ColorSaturation,
+        Property::Rotation,
+        Property::Skew,
+        Property::Stretch,
+    ];
+
+    pub fn get(&self, fp: &FractalProperties) -> Float {
+        match self {
+            Property::CenterX => fp.center_x,
+            Property::CenterY => fp.center_y,
+            Property::Zoom => fp.zoom,
+            Property::MaxIter => fp.max_iter,
+            Property::ColorOffset => fp.color_offset,
+            Property::ColorSaturation => fp.color_saturation,
+            Property::Rotation => fp.rotation,
+            Property::Skew => fp.skew,
+            Property::Stretch => fp.stretch,
+        }
+    }
+
+    pub fn set(&self, fp: &mut FractalProperties, value: Float) {
+        match self {
+            Property::CenterX => fp.center_x = value,
+            Property::CenterY => fp.center_y = value,
+            Property::Zoom => fp.zoom = value,
+            // Iterations are whole numbers
+            Property::MaxIter => fp.max_iter = value.round(),
+            Property::ColorOffset => fp.color_offset = value,
+            Property::ColorSaturation => fp.color_saturation = value,
+            Property::Rotation => fp.rotation = value,
+            Property::Skew => fp.skew = value,
+            Property::Stretch => fp.stretch = value,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Key {
+    /// Seconds from the start of the animation
+    pub time: Float,
+    pub value: Float,
+    /// Interpolation from this key to the next one
+    pub interpolation: Interpolation,
+}
+
+/// Keys of a single property, sorted by time
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Track {
+    pub property: Property,
+    pub keys: Vec<Key>,
+}
+
+/// Keyed animation of a view. Properties without a track keep the value of the view the
+/// timeline is applied to.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Timeline {
+    pub tracks: Vec<Track>,
+}
+
+impl Timeline {
+    /// Read a timeline, the keys in the file can be in any order
+    pub fn read(path: impl AsRef<Path>) -> io::Result<Self> {
+        let mut timeline: Timeline = serde_json::from_str(&fs::read_to_string(path)?)?;
+        timeline.sort_keys();
+        timeline
+            .validate()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        Ok(timeline)
+    }
+
+    /// Sort the keys of every track by time, for timelines that were put together by hand
+    pub fn sort_keys(&mut self) {
+        for track in &mut self.tracks {
+            track
+                .keys
+                .sort_by(|a, b| a.time.partial_cmp(&b.time).unwrap_or(Ordering::Equal));
+        }
+    }
+
+    /// Check that the keys have finite times and are sorted, see [`Timeline::sort_keys`]
+    pub fn validate(&self) -> Result<(), String> {
+        for track in &self.tracks {
+            if track.keys.iter().any(|k| !k.time.is_finite()) {
+                return Err(format!(
+                    "Keys of {:?} must have finite times",
+                    track.property
+                ));
+            }
+            if track.keys.windows(2).any(|w| w[0].time > w[1].time) {
+                return Err(format!(
+                    "Keys of {:?} aren't sorted by time",
+                    track.property
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn write(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tracks.iter().all(|track| track.keys.is_empty())
+    }
+
+    /// Time of the last key
+    pub fn duration(&self) -> Float {
+        self.tracks
+            .iter()
+            .filter_map(|track| track.keys.last())
+            .map(|key| key.time)
+            .fold(0.0, Float::max)
+    }
+
+    /// Track of `property`, if it has any keys
+    pub fn track(&self, property: Property) -> Option<&Track> {
+        self.tracks
+            .iter()
+            .find(|track| track.property == property && !track.keys.is_empty())
+    }
+
+    /// Key `value` at `time`, replacing the key that's already there
+    pub fn set_key(&mut self, property: Property, key: Key) {
+        let track = match self.tracks.iter().position(|t| t.property == property) {
+            Some(i) => &mut self.tracks[i],
+            None => {
+                self.tracks.push(Track {
+                    property,
+                    keys: vec![],
+                });
+                self.tracks.last_mut().unwrap()
+            }
+        };
+        track.keys.retain(|k| k.time != key.time);
+        let index = track.keys.partition_point(|k| k.time < key.time);
+        track.keys.insert(index, key);
+    }
+
+    /// Key every animatable property of `fp` at `time`
+    pub fn key_view(&mut self, fp: &FractalProperties, time: Float, interpolation: Interpolation) {
+        for property in Property::ALL {
+            let key = Key {
+                time,
+                value: property.get(fp),
+                interpolation,
+            };
+            self.set_key(property, key);
+        }
+    }
+
+    /// The view at `time`, starting from `fp` for everything that isn't keyed
+    pub fn properties_at(&self, fp: FractalProperties, time: Float) -> FractalProperties {
+        let mut result = fp;
+        // The zoom goes first, exponential-zoom centers follow it
+        let zoom_track = self.track(Property::Zoom);
+        if let Some(zoom) = zoom_track.and_then(|track| track.value_at(time, None)) {
+            Property::Zoom.set(&mut result, zoom);
+        }
+        for track in &self.tracks {
+            if track.property != Property::Zoom {
+                let zoom = zoom_track.map(|t| (t, result.zoom));
+                if let Some(value) = track.value_at(time, zoom) {
+                    track.property.set(&mut result, value);
+                }
+            }
+        }
+        result
+    }
+}
+
+impl Track {
+    /// Value at `time`, `zoom` is the zoom track with the zoom at `time` if there is one.
+    /// Before the first and after the last key the value is held, tracks without keys have
+    /// no value. The keys have to be sorted by time.
+    pub fn value_at(&self, time: Float, zoom: Option<(&Track, Float)>) -> Option<Float> {
+        let keys = &self.keys;
+        let next = keys.partition_point(|k| k.time <= time);
+        if next == 0 {
+            return keys.first().map(|k| k.value);
+        }
+        if next == keys.len() {
+            return Some(keys[next - 1].value);
+        }
+
+        let (a, b) = (&keys[next - 1], &keys[next]);
+        let t = (time - a.time) / (b.time - a.time);
+        let value = match a.interpolation {
+            Interpolation::Linear => lerp(a.value, b.value, t),
+            Interpolation::Smoothstep => lerp(a.value, b.value, t * t * (3.0 - 2.0 * t)),
+            Interpolation::CatmullRom => {
+                let before = keys[next.saturating_sub(2)].value;
+                let after = keys[(next + 1).min(keys.len() - 1)].value;
+                catmull_rom(before, a.value, b.value, after, t)
+            }
+            Interpolation::ExponentialZoom => match (self.property, zoom) {
+                (Property::CenterX | Property::CenterY, Some((zoom_track, zoom))) => {
+                    // Move by the same fraction that the view size changed by, the zoom track
+                    // has keys or it wouldn't have been passed
+                    let size_a = 1.0 / zoom_track.value_at(a.time, None)?;
+                    let size_b = 1.0 / zoom_track.value_at(b.time, None)?;
+                    if size_a == size_b {
+                        lerp(a.value, b.value, t)
+                    } else {
+                        lerp(a.value, b.value, (size_a - 1.0 / zoom) / (size_a - size_b))
+                    }
+                }
+                _ if a.value > 0.0 && b.value > 0.0 => a.value * (b.value / a.value).powf(t),
+                _ => lerp(a.value, b.value, t),
+            },
+        };
+        Some(value)
+    }
+}
+
+fn lerp(a: Float, b: Float, t: Float) -> Float {
+    a + (b - a) * t
+}
+
+/// Uniform Catmull-Rom spline between `p1` and `p2`
+fn catmull_rom(p0: Float, p1: Float, p2: Float, p3: Float, t: Float) -> Float {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    0.5 * (2.0 * p1
+        + (p2 - p0) * t
+        + (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3) * t2
+        + (3.0 * p1 - p0 - 3.0 * p2 + p3) * t3)
+}
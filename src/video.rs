@@ -0,0 +1,628 @@
+use std::{
+    borrow::Cow,
+    fs, io,
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use fontdue::Font;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    algorithms::mandelbrot::{AlgorithmType, Float, FractalProperties},
+    animation::{AnimationEncoder, AnimationFormat},
+    batch::{Renderer, View},
+    export,
+    location::Location,
+    overlay::Overlay,
+    reproject::{Keyframe, ZoomKeyframes},
+    timeline::Timeline,
+};
+
+/// Job file keeping the progress of a video, inside its output directory
+pub const JOB_FILE: &str = "video_job.json";
+
+/// Placeholder in `VideoSettings::file_pattern` replaced by the frame number
+pub const FRAME_PLACEHOLDER: &str = "{frame}";
+
+/// Zoom video settings. Frames are written as separate images into `output_dir` and/or streamed
+/// into an animation.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct VideoSettings {
+    pub output_dir: PathBuf,
+    /// Name of every frame, `{frame}` is replaced by the zero padded frame number and the
+    /// extension picks the image format
+    pub file_pattern: String,
+    /// Write every frame as a separate image
+    pub save_frames: bool,
+    /// Also stream the frames into this animation, the format is picked by the extension
+    pub animation: Option<PathBuf>,
+    pub fps: Float,
+    /// Length of the video in seconds, unless there's a timeline
+    pub duration: Float,
+    pub start_zoom: Float,
+    pub end_zoom: Float,
+    /// Keyed animation to render instead of the straight zoom from `start_zoom` to `end_zoom`,
+    /// the video lasts until its last key
+    pub timeline: Option<Timeline>,
+    /// Render a keyframe at every doubling of the zoom and reproject the frames from those,
+    /// instead of rendering every frame
+    pub reproject: bool,
+    /// Sub-frames blended into every frame for motion blur, 1 renders every frame once
+    pub motion_samples: u32,
+    /// Part of the time between two frames the sub-frames are spread over, in degrees. 360
+    /// blurs up to the neighbouring frames, 180 is the classic film look.
+    pub shutter_angle: Float,
+    /// Text drawn onto every frame
+    pub overlay: Option<Overlay>,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Default for VideoSettings {
+    fn default() -> Self {
+        Self {
+            output_dir: PathBuf::from("video"),
+            file_pattern: format!("frame_{}.png", FRAME_PLACEHOLDER),
+            save_frames: true,
+            animation: None,
+            fps: 30.0,
+            duration: 10.0,
+            start_zoom: FractalProperties::default().zoom,
+            end_zoom: 1e6,
+            timeline: None,
+            reproject: false,
+            motion_samples: 1,
+            shutter_angle: 180.0,
+            overlay: None,
+            width: 1280,
+            height: 720,
+        }
+    }
+}
+
+impl VideoSettings {
+    /// Length of the video in seconds
+    pub fn length(&self) -> Float {
+        match &self.timeline {
+            Some(timeline) => timeline.duration(),
+            None => self.duration,
+        }
+    }
+
+    pub fn total_frames(&self) -> u32 {
+        ((self.fps * self.length()).round() as u32).max(1)
+    }
+
+    /// Time of the given frame in seconds, the last frame shows the end of the video
+    pub fn frame_time(&self, frame: u32) -> Float {
+        let frames = self.total_frames();
+        if frames < 2 {
+            return 0.0;
+        }
+        frame as Float * self.length() / (frames - 1) as Float
+    }
+
+    /// Zoom multiplier between two consecutive frames, the same for the whole video so that the
+    /// zoom speed looks constant.
+    pub fn zoom_step(&self) -> Float {
+        let frames = self.total_frames();
+        if frames < 2 {
+            return 1.0;
+        }
+        (self.end_zoom / self.start_zoom).powf(1.0 / (frames - 1) as Float)
+    }
+
+    /// Zoom of the given frame, going exponentially from `start_zoom` to `end_zoom`
+    pub fn zoom_at(&self, frame: u32) -> Float {
+        if frame + 1 >= self.total_frames() {
+            return self.end_zoom;
+        }
+        self.start_zoom * self.zoom_step().powi(frame as i32)
+    }
+
+    /// View of the given frame, `fp` gives everything that isn't animated
+    pub fn frame_properties(&self, fp: FractalProperties, frame: u32) -> FractalProperties {
+        match &self.timeline {
+            Some(timeline) => timeline.properties_at(fp, self.frame_time(frame)),
+            None => FractalProperties {
+                zoom: self.zoom_at(frame),
+                ..fp
+            },
+        }
+    }
+
+    /// View at `time` seconds into the video, which doesn't have to fall on a frame
+    pub fn properties_at(&self, fp: FractalProperties, time: Float) -> FractalProperties {
+        match &self.timeline {
+            Some(timeline) => timeline.properties_at(fp, time),
+            None => FractalProperties {
+                zoom: self.start_zoom
+                    * (self.end_zoom / self.start_zoom).powf(time / self.length()),
+                ..fp
+            },
+        }
+    }
+
+    /// Views of the sub-frames blended into the given frame, spread evenly over the open
+    /// shutter around it and kept within the video, so that the first and last frame don't
+    /// show views from before the start or after the end. The `ss_factor` samples of every
+    /// pixel are shared out among the sub-frames, so that motion blur doesn't multiply the
+    /// render time.
+    pub fn sample_properties(&self, fp: FractalProperties, frame: u32) -> Vec<FractalProperties> {
+        let samples = self.motion_samples.max(1);
+        if samples == 1 {
+            return vec![self.frame_properties(fp, frame)];
+        }
+        let frames = self.total_frames();
+        let interval = if frames < 2 {
+            0.0
+        } else {
+            self.length() / (frames - 1) as Float
+        };
+        let shutter = interval * self.shutter_angle / 360.0;
+        let ss_factor = ((fp.ss_factor * fp.ss_factor) as Float / samples as Float)
+            .sqrt()
+            .round()
+            .max(1.0) as i32;
+        (0..samples)
+            .map(|sample| {
+                let offset = shutter * ((sample as Float + 0.5) / samples as Float - 0.5);
+                let time = self.frame_time(frame) + offset;
+                FractalProperties {
+                    ss_factor,
+                    ..self.properties_at(fp, time.clamp(0.0, self.length()))
+                }
+            })
+            .collect()
+    }
+
+    /// Keyframes the frames are reprojected from
+    pub fn keyframes(&self) -> ZoomKeyframes {
+        ZoomKeyframes::new(self.start_zoom, self.end_zoom)
+    }
+
+    /// Keyframe the given frame is reprojected from, the one covering all of its sub-frames
+    pub fn keyframe_index(&self, fp: FractalProperties, frame: u32) -> u32 {
+        let zoom = self
+            .sample_properties(fp, frame)
+            .iter()
+            .map(|sample| sample.zoom)
+            .fold(Float::INFINITY, Float::min);
+        self.keyframes().index_of(zoom)
+    }
+
+    /// Reproject the given frame from `keyframe`, blending its sub-frames
+    pub fn reproject_frame(
+        &self,
+        keyframe: &Keyframe,
+        fp: FractalProperties,
+        frame: u32,
+    ) -> Vec<[u8; 3]> {
+        let mut blend = Blend::default();
+        for sample in self.sample_properties(fp, frame) {
+            blend.add(&keyframe.reproject(self.width, self.height, sample.zoom));
+        }
+        blend.finish()
+    }
+
+    pub fn frame_path(&self, frame: u32) -> PathBuf {
+        let name = self
+            .file_pattern
+            .replace(FRAME_PLACEHOLDER, &format!("{:05}", frame));
+        self.output_dir.join(name)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if !(self.fps > 0.0 && self.length() > 0.0) {
+            return Err("Frame rate and duration must be positive".to_string());
+        }
+        if !(self.start_zoom > 0.0 && self.end_zoom > 0.0) {
+            return Err("Start and end zoom must be positive".to_string());
+        }
+        if self.width == 0 || self.height == 0 {
+            return Err("Frame size must not be empty".to_string());
+        }
+        if self.motion_samples == 0 || !(0.0..=360.0).contains(&self.shutter_angle) {
+            return Err(
+                "Motion blur needs at least one sample and a shutter angle of 0-360°".to_string(),
+            );
+        }
+        if self.reproject && self.timeline.is_some() {
+            return Err("Only straight zooms can be reprojected, not timelines".to_string());
+        }
+        if let Some(timeline) = &self.timeline {
+            timeline.validate()?;
+        }
+        match &self.animation {
+            Some(path) if AnimationFormat::from_path(path).is_none() => {
+                return Err(format!(
+                    "Unsupported animation format {}, use .gif, .png or .y4m",
+                    path.display()
+                ));
+            }
+            None if !self.save_frames => {
+                return Err("Neither frames nor an animation would be saved".to_string());
+            }
+            _ => {}
+        }
+        if self.save_frames && !self.file_pattern.contains(FRAME_PLACEHOLDER) {
+            return Err(format!(
+                "File pattern {} has no {} placeholder",
+                self.file_pattern, FRAME_PLACEHOLDER
+            ));
+        }
+        Ok(())
+    }
+
+    /// Save a finished frame as a separate image, creating the output directory if needed
+    pub fn save_frame(
+        &self,
+        frame: u32,
+        pixels: &[[u8; 3]],
+        fp: FractalProperties,
+    ) -> io::Result<PathBuf> {
+        fs::create_dir_all(&self.output_dir)?;
+        let path = self.frame_path(frame);
+        export::save_image(&path, self.width, self.height, pixels, &Location::new(fp))?;
+        Ok(path)
+    }
+}
+
+/// Average of the sub-frames of a motion blurred frame
+#[derive(Default)]
+pub struct Blend {
+    sum: Vec<[u32; 3]>,
+    count: u32,
+}
+
+impl Blend {
+    pub fn add(&mut self, pixels: &[[u8; 3]]) {
+        if self.count == 0 {
+            self.sum = vec![[0; 3]; pixels.len()];
+        }
+        for (sum, pixel) in self.sum.iter_mut().zip(pixels) {
+            for (s, p) in sum.iter_mut().zip(pixel) {
+                *s += *p as u32;
+            }
+        }
+        self.count += 1;
+    }
+
+    /// The blended frame, starting over for the next one
+    pub fn finish(&mut self) -> Vec<[u8; 3]> {
+        let count = std::mem::take(&mut self.count).max(1);
+        std::mem::take(&mut self.sum)
+            .iter()
+            .map(|sum| sum.map(|s| ((s + count / 2) / count) as u8))
+            .collect()
+    }
+}
+
+/// Progress of a video export, written into the output directory after every frame so that an
+/// interrupted export can be resumed.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct VideoJob {
+    pub settings: VideoSettings,
+    /// View the video was started from
+    pub fp: FractalProperties,
+    /// Frames written so far
+    pub completed_frames: u32,
+}
+
+impl VideoJob {
+    /// Job file of a video writing into `output_dir`
+    pub fn path(output_dir: impl AsRef<Path>) -> PathBuf {
+        output_dir.as_ref().join(JOB_FILE)
+    }
+
+    pub fn read(path: impl AsRef<Path>) -> io::Result<Self> {
+        let data = fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&data)?)
+    }
+
+    /// Replace the job file, the old one stays until the new one is completely written
+    pub fn write(&self) -> io::Result<()> {
+        fs::create_dir_all(&self.settings.output_dir)?;
+        let path = Self::path(&self.settings.output_dir);
+        let partial = export::partial_path(&path);
+        fs::write(&partial, serde_json::to_string_pretty(self)?)?;
+        fs::rename(partial, path)
+    }
+}
+
+/// Writes the finished frames of a video, as images and into the animation if there is one.
+/// The progress is kept in a [`VideoJob`] file until the last frame is written.
+pub struct VideoWriter {
+    pub settings: VideoSettings,
+    /// View the video was started from
+    pub fp: FractalProperties,
+    completed_frames: u32,
+    animation: Option<AnimationEncoder>,
+    /// Overlay with its font loaded
+    overlay: Option<(Overlay, Font)>,
+}
+
+impl VideoWriter {
+    /// Start a video of the view `fp`. `default_font` is used for an overlay that doesn't name
+    /// a font file.
+    pub fn new(
+        settings: VideoSettings,
+        fp: FractalProperties,
+        default_font: Option<&[u8]>,
+    ) -> io::Result<Self> {
+        let writer = Self::create(settings, fp, default_font)?;
+        writer.write_job()?;
+        Ok(writer)
+    }
+
+    /// Continue the video of a job file. Frames already on disk are kept and fed into the
+    /// animation again, which is always written from the start. Frames that were only written
+    /// into the animation have to be rendered again.
+    pub fn resume(job: VideoJob, default_font: Option<&[u8]>) -> io::Result<Self> {
+        let mut writer = Self::create(job.settings, job.fp, default_font)?;
+        let (width, height) = (writer.settings.width, writer.settings.height);
+        while writer.settings.save_frames && writer.completed_frames < job.completed_frames {
+            let path = writer.settings.frame_path(writer.completed_frames);
+            if !path.exists() {
+                break;
+            }
+            if let Some(animation) = &mut writer.animation {
+                let img = image::open(&path)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+                    .to_rgb8();
+                if img.dimensions() != (width, height) {
+                    break;
+                }
+                let pixels: Vec<[u8; 3]> = img.pixels().map(|p| p.0).collect();
+                animation.add_frame(&pixels)?;
+            }
+            writer.completed_frames += 1;
+        }
+        writer.write_job()?;
+        Ok(writer)
+    }
+
+    fn create(
+        settings: VideoSettings,
+        fp: FractalProperties,
+        default_font: Option<&[u8]>,
+    ) -> io::Result<Self> {
+        let overlay = match &settings.overlay {
+            Some(overlay) => Some((overlay.clone(), overlay.load_font(default_font)?)),
+            None => None,
+        };
+        let animation = match &settings.animation {
+            Some(path) => {
+                if let Some(dir) = path.parent() {
+                    fs::create_dir_all(dir)?;
+                }
+                Some(AnimationEncoder::create(
+                    path,
+                    settings.width,
+                    settings.height,
+                    settings.fps,
+                    settings.total_frames(),
+                )?)
+            }
+            None => None,
+        };
+        Ok(Self {
+            settings,
+            fp,
+            completed_frames: 0,
+            animation,
+            overlay,
+        })
+    }
+
+    /// Frame to be added next
+    pub fn next_frame(&self) -> u32 {
+        self.completed_frames
+    }
+
+    fn write_job(&self) -> io::Result<()> {
+        VideoJob {
+            settings: self.settings.clone(),
+            fp: self.fp,
+            completed_frames: self.completed_frames,
+        }
+        .write()
+    }
+
+    /// Write the next frame with the overlay drawn onto it, frames have to be added in order.
+    pub fn add_frame(
+        &mut self,
+        frame: u32,
+        pixels: &[[u8; 3]],
+        fp: FractalProperties,
+    ) -> io::Result<()> {
+        let mut pixels = Cow::Borrowed(pixels);
+        if let Some((overlay, font)) = &self.overlay {
+            let settings = &self.settings;
+            let text = overlay.text(&fp, Some(frame), Some(settings.frame_time(frame)));
+            overlay.draw(
+                font,
+                &text,
+                settings.width,
+                settings.height,
+                pixels.to_mut(),
+            );
+        }
+        if self.settings.save_frames {
+            self.settings.save_frame(frame, &pixels, fp)?;
+        }
+        if let Some(animation) = &mut self.animation {
+            animation.add_frame(&pixels)?;
+        }
+        self.completed_frames = frame + 1;
+        self.write_job()
+    }
+
+    /// Finish the animation. The job file is removed once every frame is written, a stopped
+    /// video keeps it to be resumed later.
+    pub fn finish(self) -> io::Result<()> {
+        if let Some(animation) = self.animation {
+            animation.finish()?;
+        }
+        if self.completed_frames >= self.settings.total_frames() {
+            fs::remove_file(VideoJob::path(&self.settings.output_dir))?;
+        }
+        Ok(())
+    }
+}
+
+/// Render and save every frame of the video without a window. `on_frame` is called after every
+/// written frame.
+pub fn export_video<F>(
+    settings: &VideoSettings,
+    fp: FractalProperties,
+    algorithm: &AlgorithmType,
+    renderer: &mut Renderer,
+    on_frame: F,
+) -> Result<(), String>
+where
+    F: Fn(u32),
+{
+    settings.validate()?;
+    let writer = VideoWriter::new(settings.clone(), fp, None)
+        .map_err(|e| format!("Failed creating the video: {}", e))?;
+    render_video(
+        writer,
+        1,
+        |views| renderer.render_views(algorithm, views),
+        on_frame,
+    )
+}
+
+/// Continue an interrupted video from its job file, see [`VideoWriter::resume`].
+pub fn resume_video<F>(
+    job_path: impl AsRef<Path>,
+    algorithm: &AlgorithmType,
+    renderer: &mut Renderer,
+    on_frame: F,
+) -> Result<(), String>
+where
+    F: Fn(u32),
+{
+    let writer = resume_writer(job_path)?;
+    render_video(
+        writer,
+        1,
+        |views| renderer.render_views(algorithm, views),
+        on_frame,
+    )
+}
+
+/// Read the job file of an interrupted video and reopen its writer
+pub fn resume_writer(job_path: impl AsRef<Path>) -> Result<VideoWriter, String> {
+    let job_path = job_path.as_ref();
+    let job = VideoJob::read(job_path)
+        .map_err(|e| format!("Failed reading {}: {}", job_path.display(), e))?;
+    job.settings.validate()?;
+    let writer =
+        VideoWriter::resume(job, None).map_err(|e| format!("Failed resuming the video: {}", e))?;
+    println!(
+        "Resuming at frame {}/{}",
+        writer.next_frame() + 1,
+        writer.settings.total_frames()
+    );
+    Ok(writer)
+}
+
+/// Render the frames the writer is still missing, `frames_per_batch` at a time. `render`
+/// renders every view of a batch and returns the images in order, on this machine or others.
+pub fn render_video<R, F>(
+    mut writer: VideoWriter,
+    frames_per_batch: u32,
+    mut render: R,
+    on_frame: F,
+) -> Result<(), String>
+where
+    R: FnMut(&[View]) -> Result<Vec<Vec<[u8; 3]>>, String>,
+    F: Fn(u32),
+{
+    let start = Instant::now();
+    let (settings, fp) = (writer.settings.clone(), writer.fp);
+    let keyframes = settings.keyframes();
+    // Keyframes the current batch is reprojected from
+    let mut cached: Vec<Keyframe> = vec![];
+    let total_frames = settings.total_frames();
+    let mut next_frame = writer.next_frame();
+    while next_frame < total_frames {
+        let frames: Vec<u32> =
+            (next_frame..(next_frame + frames_per_batch.max(1)).min(total_frames)).collect();
+        let images: Vec<Vec<[u8; 3]>> = if settings.reproject {
+            let mut indices: Vec<u32> = frames
+                .iter()
+                .map(|frame| settings.keyframe_index(fp, *frame))
+                .collect();
+            indices.dedup();
+            cached.retain(|keyframe| indices.contains(&keyframe.index));
+            indices.retain(|index| cached.iter().all(|keyframe| keyframe.index != *index));
+
+            let (width, height) = ZoomKeyframes::size(settings.width, settings.height);
+            let views: Vec<View> = indices
+                .iter()
+                .map(|index| (width, height, keyframes.properties(fp, *index)))
+                .collect();
+            for (index, pixels) in indices.iter().zip(render(&views)?) {
+                cached.push(Keyframe {
+                    index: *index,
+                    zoom: keyframes.zoom(*index),
+                    width,
+                    height,
+                    pixels,
+                });
+                println!("Rendered keyframe {}/{}", index + 1, keyframes.count);
+            }
+            frames
+                .iter()
+                .map(|frame| {
+                    let index = settings.keyframe_index(fp, *frame);
+                    let keyframe = cached.iter().find(|k| k.index == index).unwrap();
+                    settings.reproject_frame(keyframe, fp, *frame)
+                })
+                .collect()
+        } else {
+            let samples: Vec<Vec<FractalProperties>> = frames
+                .iter()
+                .map(|frame| settings.sample_properties(fp, *frame))
+                .collect();
+            let views: Vec<View> = samples
+                .iter()
+                .flatten()
+                .map(|sample| (settings.width, settings.height, *sample))
+                .collect();
+            let mut rendered = render(&views)?.into_iter();
+            samples
+                .iter()
+                .map(|samples| {
+                    let mut blend = Blend::default();
+                    for pixels in rendered.by_ref().take(samples.len()) {
+                        blend.add(&pixels);
+                    }
+                    blend.finish()
+                })
+                .collect()
+        };
+
+        for (frame, img) in frames.iter().zip(images) {
+            let frame_fp = settings.frame_properties(fp, *frame);
+            writer
+                .add_frame(*frame, &img, frame_fp)
+                .map_err(|e| format!("Failed saving frame {}: {}", frame, e))?;
+            on_frame(*frame);
+        }
+        next_frame += frames.len() as u32;
+    }
+    writer
+        .finish()
+        .map_err(|e| format!("Failed finishing the video: {}", e))?;
+    println!(
+        "Finished rendering the video in: {}s",
+        start.elapsed().as_secs()
+    );
+    Ok(())
+}
@@ -0,0 +1,98 @@
+use std::{
+    fs::{self, File},
+    path::{Path, PathBuf},
+};
+
+use brot_rs::animation::AnimationEncoder;
+
+mod common;
+
+const WIDTH: u32 = 6;
+const HEIGHT: u32 = 4;
+
+fn encode(dir: &Path, name: &str, frames: u32) -> PathBuf {
+    let path = dir.join(name);
+    let mut encoder = AnimationEncoder::create(&path, WIDTH, HEIGHT, 25.0, frames).unwrap();
+    for frame in 0..frames {
+        let pixels: Vec<[u8; 3]> = (0..WIDTH * HEIGHT)
+            .map(|i| [(i * 10) as u8, (frame * 80) as u8, 200])
+            .collect();
+        encoder.add_frame(&pixels).unwrap();
+    }
+    encoder.finish().unwrap();
+    path
+}
+
+#[test]
+fn writes_animated_gif() {
+    let dir = common::TempDir::new("animation_gif");
+    let path = encode(&dir, "anim.gif", 3);
+    let mut decoder = gif::DecodeOptions::new()
+        .read_info(File::open(&path).unwrap())
+        .unwrap();
+    let mut frames = 0;
+    while let Some(frame) = decoder.read_next_frame().unwrap() {
+        assert_eq!((frame.width, frame.height), (WIDTH as u16, HEIGHT as u16));
+        assert_eq!(frame.delay, 4);
+        frames += 1;
+    }
+    assert_eq!(frames, 3);
+}
+
+#[test]
+fn writes_apng() {
+    let dir = common::TempDir::new("animation_apng");
+    let path = encode(&dir, "anim.png", 3);
+    let reader = png::Decoder::new(File::open(&path).unwrap())
+        .read_info()
+        .unwrap();
+    let animation = reader.info().animation_control.unwrap();
+    assert_eq!(animation.num_frames, 3);
+}
+
+#[test]
+fn writes_y4m_stream() {
+    let dir = common::TempDir::new("animation_y4m");
+    let path = encode(&dir, "anim.y4m", 3);
+    let data = fs::read(&path).unwrap();
+
+    let header = b"YUV4MPEG2 W6 H4 F25:1 Ip A1:1 C444\n";
+    assert!(data.starts_with(header));
+    let frame_size = b"FRAME\n".len() + (WIDTH * HEIGHT * 3) as usize;
+    assert_eq!(data.len(), header.len() + 3 * frame_size);
+}
+
+#[test]
+fn rejects_unknown_formats() {
+    let path = std::env::temp_dir().join("brot_rs_anim.mp4");
+    assert!(AnimationEncoder::create(path, WIDTH, HEIGHT, 25.0, 1).is_err());
+}
+
+#[test]
+fn stopped_apng_plays_the_written_frames() {
+    let dir = common::TempDir::new("animation_stopped");
+    let path = dir.join("stopped.png");
+    let mut encoder = AnimationEncoder::create(&path, WIDTH, HEIGHT, 120.5, 5).unwrap();
+    for _ in 0..2 {
+        encoder
+            .add_frame(&vec![[9, 8, 7]; (WIDTH * HEIGHT) as usize])
+            .unwrap();
+    }
+    encoder.finish().unwrap();
+
+    let mut reader = png::Decoder::new(File::open(&path).unwrap())
+        .read_info()
+        .unwrap();
+    assert_eq!(reader.info().animation_control.unwrap().num_frames, 2);
+    let control = reader.info().frame_control.unwrap();
+    assert_eq!((control.delay_num, control.delay_den), (2, 241));
+    let mut buf = vec![0; reader.output_buffer_size()];
+    for _ in 0..2 {
+        reader.next_frame(&mut buf).unwrap();
+    }
+
+    let empty = dir.join("empty.png");
+    let encoder = AnimationEncoder::create(&empty, WIDTH, HEIGHT, 25.0, 5).unwrap();
+    encoder.finish().unwrap();
+    assert!(!empty.exists());
+}
@@ -0,0 +1,45 @@
+use std::fs;
+
+use brot_rs::batch::{run_batch, JobStatus, Manifest, Renderer};
+
+mod common;
+
+#[test]
+fn renders_skips_and_reports_failures() {
+    let dir = common::TempDir::new("batch");
+    fs::write(dir.join("existing.png"), b"not rendered again").unwrap();
+
+    let manifest: Manifest = serde_json::from_str(
+        r#"{
+            "defaults": { "size": [16, 12], "algorithm": "cpu", "iterations": 50 },
+            "jobs": [
+                { "output": "whole.png" },
+                { "output": "zoomed.bmp", "center": [-0.75, 0.1], "zoom": 4.0, "size": [8, 8] },
+                { "output": "existing.png" },
+                { "output": "missing.png", "location": "does_not_exist.json" }
+            ]
+        }"#,
+    )
+    .unwrap();
+
+    let report = run_batch(&manifest, &dir, &mut Renderer::default(), false);
+    let statuses: Vec<_> = report.jobs.iter().map(|job| job.status).collect();
+    let zoomed = image::open(dir.join("zoomed.bmp")).map(|img| img.to_rgb8().dimensions());
+    let whole = image::open(dir.join("whole.png")).map(|img| img.to_rgb8().dimensions());
+    let existing = fs::read(dir.join("existing.png")).unwrap();
+
+    assert_eq!(
+        statuses,
+        [
+            JobStatus::Rendered,
+            JobStatus::Rendered,
+            JobStatus::Skipped,
+            JobStatus::Failed
+        ]
+    );
+    assert_eq!((report.rendered, report.skipped, report.failed), (2, 1, 1));
+    assert!(report.jobs[3].error.is_some());
+    assert_eq!(whole.unwrap(), (16, 12));
+    assert_eq!(zoomed.unwrap(), (8, 8));
+    assert_eq!(existing, b"not rendered again");
+}
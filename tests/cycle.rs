@@ -0,0 +1,62 @@
+use std::fs::File;
+
+use brot_rs::{
+    algorithms::{coloring::calculate_pixel_color, mandelbrot::FractalProperties},
+    batch::Renderer,
+    cycle::{export_cycle, CycleSettings},
+};
+
+mod common;
+
+#[test]
+fn loops_the_palette() {
+    let settings = CycleSettings {
+        frames: 12,
+        cycles: 2,
+        ..CycleSettings::default()
+    };
+    assert_eq!(settings.phase(0), 0.0);
+    assert_eq!(settings.phase(3), 180.0);
+    assert_eq!(settings.phase(6), 360.0);
+
+    let fp = FractalProperties::default();
+    let iterations = [1.0, 5.5, 20.0, fp.max_iter];
+    let first = settings.frame_pixels(&iterations, fp, 0);
+    let plain: Vec<_> = iterations
+        .iter()
+        .map(|n| calculate_pixel_color(fp, *n))
+        .collect();
+    assert_eq!(first, plain);
+    assert_ne!(settings.frame_pixels(&iterations, fp, 3), first);
+}
+
+#[test]
+fn writes_a_gif_with_loop_perfect_timing() {
+    let dir = common::TempDir::new("cycle");
+    let path = dir.join("cycle.gif");
+    let settings = CycleSettings {
+        output: path.clone(),
+        frames: 9,
+        fps: 30.0,
+        width: 8,
+        height: 6,
+        ..CycleSettings::default()
+    };
+    let fp = FractalProperties {
+        max_iter: 50.0,
+        ..FractalProperties::default()
+    };
+    let algorithm = "cpu".parse().unwrap();
+    export_cycle(&settings, fp, &algorithm, &mut Renderer::default(), |_| {}).unwrap();
+
+    let mut decoder = gif::DecodeOptions::new()
+        .read_info(File::open(&path).unwrap())
+        .unwrap();
+    let mut delays = vec![];
+    while let Some(frame) = decoder.read_next_frame().unwrap() {
+        delays.push(frame.delay);
+    }
+    // 9 frames at 30 fps last exactly 30 hundredths of a second
+    assert_eq!(delays.len(), 9);
+    assert_eq!(delays.iter().sum::<u16>(), 30);
+}
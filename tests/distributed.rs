@@ -0,0 +1,107 @@
+use std::{
+    io::{Read, Write},
+    net::TcpStream,
+    sync::mpsc,
+    thread,
+    time::Duration,
+};
+
+use brot_rs::{
+    algorithms::mandelbrot::FractalProperties,
+    batch::{Renderer, View},
+    distributed::{self, Coordinator, PROTOCOL_VERSION},
+};
+
+#[test]
+fn jobs_of_a_dead_worker_are_reassigned() {
+    let mut coordinator = Coordinator::bind("127.0.0.1:0", Duration::from_secs(30)).unwrap();
+    let address = coordinator.local_addr();
+    let views: Vec<View> = (0..6)
+        .map(|i| {
+            let fp = FractalProperties {
+                center_x: -0.5 + i as f64 * 0.1,
+                zoom: 1.0 + i as f64,
+                max_iter: 50.0,
+                ..FractalProperties::default()
+            };
+            (24 + i, 16, fp)
+        })
+        .collect();
+
+    // Takes the first job and disconnects without answering
+    let (taken, job_taken) = mpsc::channel();
+    thread::spawn(move || {
+        let mut stream = TcpStream::connect(address).unwrap();
+        stream.write_all(&[0, 0, 0, 0, 4]).unwrap();
+        stream.write_all(&PROTOCOL_VERSION.to_be_bytes()).unwrap();
+        let mut tag = [0u8];
+        stream.read_exact(&mut tag).unwrap();
+        taken.send(tag[0]).unwrap();
+    });
+    let render_views = views.clone();
+    let render = thread::spawn(move || coordinator.render(&render_views));
+
+    assert_eq!(job_taken.recv().unwrap(), 1);
+    for _ in 0..2 {
+        thread::spawn(move || {
+            let algorithm = "cpu".parse().unwrap();
+            distributed::run_worker(address, &algorithm, &mut Renderer::default())
+        });
+    }
+
+    let images = render.join().unwrap().unwrap();
+    let expected = Renderer::default()
+        .render_views(&"cpu".parse().unwrap(), &views)
+        .unwrap();
+    assert_eq!(images, expected);
+}
+
+#[test]
+fn refuses_connections_announcing_huge_messages() {
+    let coordinator = Coordinator::bind("127.0.0.1:0", Duration::from_secs(30)).unwrap();
+    let mut stream = TcpStream::connect(coordinator.local_addr()).unwrap();
+    // A HELLO claiming to be 4 GiB long is dropped right away instead of being waited for
+    stream.write_all(&[0, 0xff, 0xff, 0xff, 0xff]).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(10)))
+        .unwrap();
+    let mut buf = [0u8];
+    assert_eq!(stream.read(&mut buf).unwrap(), 0);
+    assert_eq!(coordinator.workers(), 0);
+}
+
+#[test]
+fn workers_refuse_jobs_they_cant_render() {
+    let mut coordinator = Coordinator::bind("127.0.0.1:0", Duration::from_secs(30)).unwrap();
+    let address = coordinator.local_addr();
+    thread::spawn(move || {
+        let algorithm = "cpu".parse().unwrap();
+        distributed::run_worker(address, &algorithm, &mut Renderer::default())
+    });
+
+    let invalid = FractalProperties {
+        zoom: 0.0,
+        ..FractalProperties::default()
+    };
+    let error = coordinator.render(&[(8, 8, invalid)]).unwrap_err();
+    assert!(error.contains("Zoom must be positive"), "{}", error);
+    // Answered without allocating the image
+    let huge = (100_000, 100_000, FractalProperties::default());
+    let error = coordinator.render(&[huge]).unwrap_err();
+    assert!(error.contains("Job size"), "{}", error);
+
+    // The worker is still there for valid jobs
+    let view = FractalProperties {
+        max_iter: 50.0,
+        ..FractalProperties::default()
+    };
+    assert_eq!(coordinator.render(&[(8, 8, view)]).unwrap()[0].len(), 64);
+}
+
+#[test]
+fn gives_up_without_workers() {
+    let mut coordinator = Coordinator::bind("127.0.0.1:0", Duration::from_millis(200)).unwrap();
+    let view = (8, 8, FractalProperties::default());
+    let error = coordinator.render(&[view]).unwrap_err();
+    assert!(error.contains("No worker"), "{}", error);
+}
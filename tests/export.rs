@@ -0,0 +1,29 @@
+use brot_rs::{
+    algorithms::mandelbrot::FractalProperties,
+    export::{read_png_location, save_png},
+    location::Location,
+};
+
+mod common;
+
+#[test]
+fn png_keeps_the_location() {
+    let dir = common::TempDir::new("export");
+    let path = dir.join("location.png");
+    let location = Location::new(FractalProperties {
+        center_x: -0.743_643_887_037_158_7,
+        center_y: 0.131_825_904_205_311_97,
+        zoom: 3.2e13,
+        rotation: 45.0,
+        ..FractalProperties::default()
+    });
+    let pixels: Vec<[u8; 3]> = (0..12).map(|i| [i * 20, 255 - i * 20, 7]).collect();
+    save_png(&path, 4, 3, &pixels, &location).unwrap();
+
+    let read = read_png_location(&path);
+    let img = image::open(&path).unwrap().to_rgb8();
+
+    assert_eq!(read.unwrap(), location);
+    assert_eq!(img.dimensions(), (4, 3));
+    assert_eq!(img.get_pixel(1, 2).0, pixels[9]);
+}
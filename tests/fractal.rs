@@ -0,0 +1,95 @@
+use std::{
+    future::Future,
+    sync::Arc,
+    task::{Context, Poll, Wake},
+    thread::{self, Thread},
+};
+
+use brot_rs::{batch::Renderer, Backend, FractalProperties, FractalRenderer, RenderEvent};
+
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+/// Minimal executor, parks the thread until the future wakes it
+fn block_on<F: Future>(future: F) -> F::Output {
+    let waker = Arc::new(ThreadWaker(thread::current())).into();
+    let mut cx = Context::from_waker(&waker);
+    let mut future = Box::pin(future);
+    loop {
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => thread::park(),
+        }
+    }
+}
+
+fn view() -> FractalProperties {
+    FractalProperties {
+        center_x: -0.75,
+        center_y: 0.1,
+        zoom: 4.0,
+        max_iter: 100.0,
+        ..FractalProperties::default()
+    }
+}
+
+#[test]
+fn builder_renders_image_buffers() {
+    let mut renderer = FractalRenderer::builder()
+        .backend("cpu".parse().unwrap())
+        .size(70, 50)
+        .properties(view())
+        .build()
+        .unwrap();
+    let image = renderer.render().unwrap();
+    let expected = Renderer::default()
+        .render(&"cpu".parse().unwrap(), 70, 50, view())
+        .unwrap();
+    assert_eq!(image.dimensions(), (70, 50));
+    assert_eq!(image.into_raw(), expected.concat());
+
+    assert!(FractalRenderer::builder().size(0, 10).build().is_err());
+    assert!(FractalRenderer::builder().zoom(-1.0).spawn().is_err());
+    assert!(FractalRenderer::builder().zoom(f64::NAN).build().is_err());
+    assert!(FractalRenderer::builder()
+        .supersampling(100)
+        .build()
+        .is_err());
+}
+
+#[test]
+fn spawned_renders_stream_tiles_and_resolve() {
+    let builder = FractalRenderer::builder()
+        .backend(Backend::Cpu)
+        .size(150, 90)
+        .properties(view());
+    let expected = builder.clone().build().unwrap().render().unwrap();
+
+    let mut task = builder.clone().spawn().unwrap();
+    let mut streamed = vec![[0u8; 3]; 150 * 90];
+    let mut last_progress = 0;
+    for event in task.by_ref() {
+        match event {
+            RenderEvent::Tile(tile) => {
+                for (row, pixels) in tile.pixels.chunks(tile.width as usize).enumerate() {
+                    let start = (tile.y as usize + row) * 150 + tile.x as usize;
+                    streamed[start..start + pixels.len()].copy_from_slice(pixels);
+                }
+            }
+            RenderEvent::Progress {
+                completed_pixels, ..
+            } => last_progress = last_progress.max(completed_pixels),
+        }
+    }
+    assert_eq!(last_progress, 150 * 90);
+    assert_eq!(streamed.concat(), expected.as_raw()[..]);
+    assert_eq!(task.wait().unwrap(), expected);
+
+    let image = block_on(builder.spawn().unwrap()).unwrap();
+    assert_eq!(image, expected);
+}
@@ -0,0 +1,107 @@
+use std::fs;
+
+use brot_rs::{
+    algorithms::mandelbrot::FractalProperties,
+    history::{History, MAX_STATES},
+};
+
+mod common;
+
+fn view(zoom: f64) -> FractalProperties {
+    FractalProperties {
+        zoom,
+        ..FractalProperties::default()
+    }
+}
+
+#[test]
+fn new_views_drop_the_forward_states() {
+    let mut history = History::default();
+    assert!(history.push(view(1.0)));
+    assert!(history.push(view(2.0)));
+    assert!(!history.push(view(2.0)));
+    assert_eq!(history.states().len(), 3);
+
+    // Back twice, then forward once
+    assert_eq!(history.go_to(0), Some(FractalProperties::default()));
+    assert!(!history.can_undo() && history.can_redo());
+    assert_eq!(history.go_to(1), Some(view(1.0)));
+    assert!(history.can_undo() && history.can_redo());
+
+    assert!(history.push(view(3.0)));
+    assert!(!history.can_redo());
+    assert_eq!(
+        history.states(),
+        [FractalProperties::default(), view(1.0), view(3.0)]
+    );
+    assert_eq!(history.current(), view(3.0));
+    assert_eq!(history.go_to(3), None);
+    assert_eq!(history.current_index(), 2);
+}
+
+#[test]
+fn keeps_the_newest_states() {
+    let mut history = History::default();
+    for i in 1..=MAX_STATES + 10 {
+        history.push(view(i as f64));
+    }
+    assert_eq!(history.states().len(), MAX_STATES);
+    assert_eq!(history.current_index(), MAX_STATES - 1);
+    assert_eq!(history.states()[0], view(11.0));
+    assert_eq!(history.current(), view((MAX_STATES + 10) as f64));
+}
+
+#[test]
+fn persists_as_json() {
+    let dir = common::TempDir::new("history");
+    let path = dir.join("history.json");
+    let mut history = History::default();
+    history.push(view(1.0));
+    history.push(view(2.0));
+    history.go_to(1);
+    history.save(&path).unwrap();
+
+    let loaded = History::load_or_default(&path);
+    assert_eq!(loaded.states(), history.states());
+    assert_eq!(loaded.current_index(), 1);
+
+    // Missing, broken and inconsistent files give a fresh history
+    let fresh = History::default();
+    assert_eq!(
+        History::load_or_default(dir.join("missing.json")).states(),
+        fresh.states()
+    );
+    fs::write(&path, "not json").unwrap();
+    assert_eq!(History::load_or_default(&path).states(), fresh.states());
+    let json = serde_json::to_string(&history).unwrap();
+    fs::write(&path, json.replace("\"current\":1", "\"current\":7")).unwrap();
+    assert_eq!(History::load_or_default(&path).states(), fresh.states());
+}
+
+#[test]
+fn views_that_cant_be_rendered_give_a_fresh_history() {
+    let dir = common::TempDir::new("history_invalid");
+    let path = dir.join("history.json");
+    let broken = [
+        view(-1.0),
+        FractalProperties {
+            ss_factor: 1000,
+            ..FractalProperties::default()
+        },
+        FractalProperties {
+            max_iter: 1e12,
+            ..FractalProperties::default()
+        },
+    ];
+    for fp in broken {
+        // Not the current view either, going back to it would render it
+        let mut history = History::default();
+        history.push(fp);
+        history.push(view(2.0));
+        history.save(&path).unwrap();
+        assert_eq!(
+            History::load_or_default(&path).states(),
+            History::default().states()
+        );
+    }
+}
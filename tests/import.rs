@@ -0,0 +1,80 @@
+use brot_rs::import::{import_kfr, import_xpf};
+
+const KFR: &str = "Re: -1.7490930486802469586\r
+Im: 0.0000000087314069874\r
+Zoom: 2E5\r
+Iterations: 12000\r
+IterDiv: 1.000000\r
+Rotate: 15\r
+Ratio: 360.000000\r
+Colors: 255,255,255,128,0,64,160,0,0,192,128,0,\r
+InteriorColor: 0,0,0,\r
+Smooth: 1\r
+Power: 2\r
+FractalType: 0\r
+Slopes: 0\r
+";
+
+const XPF: &str = ";Position file automatically generated by XaoS 4.2.1
+;         - a realtime interactive fractal zoomer
+;Use xaos -load <filename> to display it
+(initstate)
+(defaultpalette 0)
+(formula 'mandel)
+(view -0.7436438870 0.1318259042 0.0000040000 0.0000030000)
+(maxiter 1000)
+(angle 30)
+(outcoloring 0)
+(cycling #f)
+";
+
+#[test]
+fn imports_kalles_fraktaler() {
+    let import = import_kfr(KFR).unwrap();
+    let fp = import.location.properties;
+    assert_eq!(fp.center_x, -1.749_093_048_680_247);
+    assert_eq!(fp.center_y, 0.000_000_008_731_406_987_4);
+    assert_eq!(fp.zoom, 1e5);
+    assert_eq!(fp.max_iter, 12000.0);
+    assert_eq!(fp.rotation, 15.0);
+    // Only the palette can't be carried over
+    assert_eq!(import.warnings.len(), 1);
+}
+
+#[test]
+fn warns_about_unsupported_kalles_fraktaler_formulas() {
+    let kfr = KFR.replace("FractalType: 0", "FractalType: 1");
+    let import = import_kfr(&kfr).unwrap();
+    assert!(import.warnings.iter().any(|w| w.contains("fractal type 1")));
+}
+
+#[test]
+fn rejects_kalles_fraktaler_without_center() {
+    assert!(import_kfr("Zoom: 1\r\nIterations: 100\r\n").is_err());
+}
+
+#[test]
+fn imports_xaos() {
+    let import = import_xpf(XPF).unwrap();
+    let fp = import.location.properties;
+    assert_eq!(fp.center_x, -0.743_643_887);
+    assert_eq!(fp.center_y, 0.131_825_904_2);
+    assert!((fp.zoom - 2.0 / 0.000_003).abs() < 1e-3);
+    assert_eq!(fp.max_iter, 1000.0);
+    assert_eq!(fp.rotation, 30.0);
+    assert_eq!(import.warnings.len(), 1);
+}
+
+#[test]
+fn warns_about_unknown_xaos_commands() {
+    let xpf = format!("{}(julia #t)\n(frobnicate 1 2)\n", XPF);
+    let import = import_xpf(&xpf).unwrap();
+    assert!(import.warnings.iter().any(|w| w.contains("Julia")));
+    assert!(import.warnings.iter().any(|w| w.contains("frobnicate")));
+}
+
+#[test]
+fn rejects_xaos_without_view() {
+    assert!(import_xpf("(initstate)\n(maxiter 100)\n").is_err());
+    assert!(import_xpf("(view 0 0 1 1").is_err());
+}
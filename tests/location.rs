@@ -0,0 +1,98 @@
+use brot_rs::{
+    algorithms::mandelbrot::FractalProperties,
+    location::{Location, LOCATION_VERSION},
+};
+
+mod common;
+
+fn deep_zoom() -> FractalProperties {
+    FractalProperties {
+        center_x: -0.743_643_887_037_158_7,
+        center_y: 0.131_825_904_205_311_97,
+        zoom: 3.2e13,
+        max_iter: 5000.0,
+        ss_factor: 3,
+        color_offset: 12.345_678_901_234_567,
+        color_saturation: 0.1 + 0.2,
+        rotation: -33.333_333_333_333_336,
+        skew: 0.25,
+        stretch: 1.5,
+    }
+}
+
+#[test]
+fn round_trips_through_json() {
+    let location = Location::new(deep_zoom());
+    let parsed = Location::parse(&location.to_json()).unwrap();
+    assert_eq!(parsed, location);
+}
+
+#[test]
+fn keeps_every_bit_of_the_coordinates() {
+    let fp = deep_zoom();
+    let parsed = Location::parse(&Location::new(fp).to_json()).unwrap();
+    assert_eq!(parsed.properties.center_x.to_bits(), fp.center_x.to_bits());
+    assert_eq!(parsed.properties.center_y.to_bits(), fp.center_y.to_bits());
+    assert_eq!(parsed.properties.zoom.to_bits(), fp.zoom.to_bits());
+}
+
+#[test]
+fn round_trips_through_a_file() {
+    let dir = common::TempDir::new("location");
+    let path = dir.join("location.json");
+    let location = Location::new(deep_zoom());
+    location.write(&path).unwrap();
+    let read = Location::read(&path);
+    assert_eq!(read.unwrap(), location);
+}
+
+#[test]
+fn rejects_newer_versions() {
+    let mut location = Location::new(deep_zoom());
+    location.version = LOCATION_VERSION + 1;
+    assert!(Location::parse(&location.to_json()).is_err());
+}
+
+#[test]
+fn fills_in_missing_properties() {
+    let data = r#"{
+        "version": 1,
+        "formula": "mandelbrot",
+        "palette": "hue_cycle",
+        "properties": { "center_x": -1.25, "zoom": 4.0 }
+    }"#;
+    let location = Location::parse(data).unwrap();
+    assert_eq!(
+        location.properties,
+        FractalProperties {
+            center_x: -1.25,
+            zoom: 4.0,
+            ..FractalProperties::default()
+        }
+    );
+}
+
+#[test]
+fn rejects_views_that_cant_be_rendered() {
+    let dir = common::TempDir::new("location_invalid");
+    let path = dir.join("location.json");
+    let broken = [
+        FractalProperties {
+            zoom: 0.0,
+            ..deep_zoom()
+        },
+        FractalProperties {
+            ss_factor: 1000,
+            ..deep_zoom()
+        },
+        FractalProperties {
+            max_iter: -5.0,
+            ..deep_zoom()
+        },
+    ];
+    for fp in broken {
+        Location::new(fp).write(&path).unwrap();
+        let error = Location::read(&path).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    }
+}
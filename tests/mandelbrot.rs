@@ -0,0 +1,77 @@
+use brot_rs::algorithms::mandelbrot::{Float, FractalProperties, ViewTransform};
+
+fn distance(a: (Float, Float), b: (Float, Float)) -> Float {
+    (a.0 - b.0).hypot(a.1 - b.1)
+}
+
+#[test]
+fn pixels_are_square_whatever_the_aspect_ratio() {
+    let fp = FractalProperties {
+        center_x: -0.5,
+        center_y: 0.25,
+        zoom: 2.0,
+        ..FractalProperties::default()
+    };
+    for (width, height) in [(1600.0, 900.0), (900.0, 1600.0), (333.0, 333.0)] {
+        let transform = ViewTransform::new(&fp, width, height);
+        let origin = transform.to_complex(0.0, 0.0);
+        let right = transform.to_complex(1.0, 0.0);
+        let down = transform.to_complex(0.0, 1.0);
+        assert!((distance(origin, right) - distance(origin, down)).abs() < 1e-15);
+        assert!(((right.0 - origin.0) * (down.0 - origin.0)).abs() < 1e-15);
+
+        // Centered, with the shorter side spanning 2 / zoom
+        let center = transform.to_complex(width / 2.0, height / 2.0);
+        assert!(distance(center, (fp.center_x, fp.center_y)) < 1e-15);
+        let span = distance(origin, right) * width.min(height);
+        assert!((span - 2.0 / fp.zoom).abs() < 1e-12);
+    }
+}
+
+#[test]
+fn pixels_map_back_under_rotation_skew_and_stretch() {
+    let views = [
+        (30.0, 0.0, 1.0),
+        (-135.0, 0.4, 1.0),
+        (0.0, -0.7, 2.5),
+        (210.0, 1.3, 0.3),
+    ];
+    for (rotation, skew, stretch) in views {
+        let fp = FractalProperties {
+            center_x: -0.743_643_887,
+            center_y: 0.131_825_904,
+            zoom: 1e6,
+            rotation,
+            skew,
+            stretch,
+            ..FractalProperties::default()
+        };
+        let transform = ViewTransform::new(&fp, 640.0, 480.0);
+        for (x, y) in [(0.0, 0.0), (639.0, 0.0), (320.5, 240.25), (17.0, 479.0)] {
+            let (cx, cy) = transform.to_complex(x, y);
+            let (px, py) = transform.to_pixel(cx, cy);
+            assert!((px - x).abs() < 1e-6 && (py - y).abs() < 1e-6);
+        }
+        // Every transform keeps the center in the middle of the image
+        let center = transform.to_complex(320.0, 240.0);
+        assert!(distance(center, (fp.center_x, fp.center_y)) < 1e-15);
+    }
+}
+
+#[test]
+fn rotation_turns_the_view_counter_clockwise() {
+    let fp = FractalProperties {
+        rotation: 90.0,
+        ..FractalProperties::default()
+    };
+    let plain = ViewTransform::new(&FractalProperties::default(), 100.0, 100.0);
+    let rotated = ViewTransform::new(&fp, 100.0, 100.0);
+    // The step to the next pixel to the right is turned by 90 degrees
+    let step = |t: &ViewTransform| {
+        let (a, b) = (t.to_complex(50.0, 50.0), t.to_complex(51.0, 50.0));
+        (b.0 - a.0, b.1 - a.1)
+    };
+    let (px, py) = step(&plain);
+    let (rx, ry) = step(&rotated);
+    assert!((rx + py).abs() < 1e-15 && (ry - px).abs() < 1e-15);
+}
@@ -0,0 +1,41 @@
+use brot_rs::{
+    algorithms::mandelbrot::FractalProperties,
+    overlay::{blend, parse_color, Anchor, Overlay},
+};
+
+#[test]
+fn fills_in_the_template() {
+    let overlay = Overlay {
+        template: "Zoom {zoom} at {center}, {iterations} iterations\n{time} #{frame}".to_string(),
+        ..Overlay::default()
+    };
+    let fp = FractalProperties {
+        center_x: -0.75,
+        center_y: 0.1,
+        zoom: 12345.0,
+        max_iter: 500.0,
+        ..FractalProperties::default()
+    };
+    assert_eq!(
+        overlay.text(&fp, Some(42), Some(83.5)),
+        "Zoom 1.234e4 at -0.7500000, 0.1000000, 500 iterations\n01:23.50 #42"
+    );
+    assert_eq!(
+        overlay.text(&fp, None, None),
+        "Zoom 1.234e4 at -0.7500000, 0.1000000, 500 iterations\n #"
+    );
+}
+
+#[test]
+fn parses_options_and_blends() {
+    assert_eq!(parse_color("#ff8000"), Ok([255, 128, 0, 255]));
+    assert_eq!(parse_color("00000080"), Ok([0, 0, 0, 128]));
+    assert!(parse_color("fff").is_err());
+    assert!(parse_color("gg0000").is_err());
+    assert_eq!("bottom-right".parse(), Ok(Anchor::BottomRight));
+    assert!("middle".parse::<Anchor>().is_err());
+
+    assert_eq!(blend([0, 100, 200], [255, 255, 255], 0.0), [0, 100, 200]);
+    assert_eq!(blend([0, 100, 200], [255, 255, 255], 1.0), [255, 255, 255]);
+    assert_eq!(blend([0, 100, 200], [200, 0, 100], 0.5), [100, 50, 150]);
+}
@@ -0,0 +1,135 @@
+use std::fs;
+
+use brot_rs::{
+    algorithms::mandelbrot::FractalProperties,
+    batch::Renderer,
+    pyramid::{self, PyramidLayout, PyramidSettings},
+};
+
+mod common;
+
+#[test]
+fn deep_zoom_levels_and_tiles() {
+    let settings = PyramidSettings {
+        output: "out/mandelbrot.dzi".into(),
+        width: 600,
+        height: 400,
+        ..PyramidSettings::default()
+    };
+    assert_eq!(settings.layout(), PyramidLayout::DeepZoom);
+    assert_eq!(settings.levels(), 11);
+    assert_eq!(settings.level_size(10), (600, 400));
+    assert_eq!(settings.level_size(9), (300, 200));
+    assert_eq!(settings.level_size(0), (1, 1));
+
+    let tiles = settings.tiles();
+    let top: Vec<_> = tiles.iter().filter(|t| t.level == 10).collect();
+    assert_eq!(top.len(), 6);
+    assert_eq!(top[1].region, (255, 0, 258, 257));
+    assert_eq!(
+        top[1].path,
+        std::path::Path::new("out/mandelbrot_files/10/1_0.png")
+    );
+    assert!(settings
+        .dzi()
+        .contains("<Size Width=\"600\" Height=\"400\"/>"));
+}
+
+#[test]
+fn levels_are_rendered_natively() {
+    let dir = common::TempDir::new("pyramid");
+    let fp = FractalProperties {
+        center_x: -0.6,
+        zoom: 0.8,
+        max_iter: 60.0,
+        ..FractalProperties::default()
+    };
+    let algorithm = "cpu".parse().unwrap();
+    let mut renderer = Renderer::default();
+
+    let xyz = PyramidSettings {
+        output: dir.join("xyz"),
+        width: 32,
+        height: 20,
+        tile_size: 16,
+        ..PyramidSettings::default()
+    };
+    pyramid::export_pyramid(&xyz, fp, &algorithm, &mut renderer, |_, _| {}).unwrap();
+    let tile = image::open(dir.join("xyz/1/1/0.png")).unwrap().to_rgb8();
+    // The top right quarter of the level, rendered at full resolution
+    let level: Vec<u8> = renderer.render(&algorithm, 32, 32, fp).unwrap().concat();
+    let expected: Vec<u8> = (0..16)
+        .flat_map(|y| level[(y * 32 + 16) * 3..(y * 32 + 32) * 3].to_vec())
+        .collect();
+
+    let dzi = PyramidSettings {
+        output: dir.join("view.dzi"),
+        width: 40,
+        height: 30,
+        tile_size: 16,
+        ..PyramidSettings::default()
+    };
+    let written = std::cell::Cell::new(0);
+    pyramid::export_pyramid(&dzi, fp, &algorithm, &mut renderer, |done, _| {
+        written.set(done)
+    })
+    .unwrap();
+    let descriptor = fs::read_to_string(dir.join("view.dzi")).unwrap();
+    let corner = image::open(dir.join("view_files/6/2_1.png")).unwrap();
+
+    assert_eq!((tile.width(), tile.height()), (16, 16));
+    let differing = tile
+        .into_raw()
+        .iter()
+        .zip(&expected)
+        .filter(|(a, b)| a.abs_diff(**b) > 1)
+        .count();
+    assert_eq!(differing, 0);
+    assert_eq!(written.get(), dzi.tiles().len());
+    assert!(descriptor.contains("TileSize=\"16\""));
+    assert_eq!((corner.width(), corner.height()), (9, 15));
+}
+
+#[test]
+fn smaller_levels_line_up_with_the_full_image() {
+    let dir = common::TempDir::new("pyramid_levels");
+    let fp = FractalProperties {
+        center_x: -0.7,
+        center_y: 0.2,
+        zoom: 1.5,
+        max_iter: 50.0,
+        ..FractalProperties::default()
+    };
+    let algorithm = "cpu".parse().unwrap();
+    let mut renderer = Renderer::default();
+    // Odd size, so the smaller levels are rounded up
+    let settings = PyramidSettings {
+        output: dir.join("view.dzi"),
+        width: 151,
+        height: 91,
+        tile_size: 256,
+        ..PyramidSettings::default()
+    };
+    pyramid::export_pyramid(&settings, fp, &algorithm, &mut renderer, |_, _| {}).unwrap();
+    let top = settings.levels() - 1;
+    assert_eq!(settings.level_size(top - 1), (76, 46));
+    let level = |level: u32| {
+        image::open(dir.join(format!("view_files/{}/0_0.png", level)))
+            .unwrap()
+            .to_rgb8()
+    };
+
+    // Pixels are sampled at their top left corner, so every pixel of a smaller level samples
+    // the same point as the pixel of the full image at its corner
+    let full = renderer.render(&algorithm, 151, 91, fp).unwrap();
+    let half = level(top - 1);
+    let differing = half
+        .enumerate_pixels()
+        .filter(|(x, y, pixel)| {
+            let expected = full[(2 * y * 151 + 2 * x) as usize];
+            (0..3).any(|c| pixel[c].abs_diff(expected[c]) > 1)
+        })
+        .count();
+    assert_eq!(differing, 0);
+    assert_eq!(level(0).get_pixel(0, 0).0, full[0]);
+}
@@ -0,0 +1,199 @@
+use std::time::Duration;
+
+use brot_rs::{
+    algorithms::mandelbrot::{AlgorithmType, Float, FractalProperties, ViewTransform},
+    renderer::{renderer_thread, RenderProgress, RendererMessage},
+};
+use crossbeam_channel::{Receiver, Sender};
+
+/// `(id, x, y, width, height, pixels)` of a `RenderedTile`
+type Tile = (u64, u32, u32, u32, u32, Vec<[u8; 3]>);
+
+/// What the renderer thread answered to one render command
+struct Answer {
+    tiles: Vec<Tile>,
+    /// `(id, completed pixels, total pixels, iterations)`
+    progress: Vec<(u64, u64, u64, u64)>,
+    image: Vec<[u8; 3]>,
+}
+
+fn render(
+    sender: &Sender<RendererMessage>,
+    receiver: &Receiver<RendererMessage>,
+    id: u64,
+    (width, height): (u32, u32),
+    fp: FractalProperties,
+) -> Answer {
+    sender
+        .send(RendererMessage::RenderCommand(
+            id,
+            width,
+            height,
+            AlgorithmType::NaiveCPU,
+            fp,
+        ))
+        .unwrap();
+    let (mut tiles, mut progress) = (vec![], vec![]);
+    for message in receiver {
+        match message {
+            RendererMessage::RenderedTile(id, x, y, w, h, pixels) => {
+                tiles.push((id, x, y, w, h, pixels))
+            }
+            RendererMessage::RenderProgress(id, completed, total, iterations) => {
+                progress.push((id, completed, total, iterations))
+            }
+            RendererMessage::RenderedImage(image, w, h) => {
+                assert_eq!((w, h), (width, height));
+                return Answer {
+                    tiles,
+                    progress,
+                    image,
+                };
+            }
+            RendererMessage::RenderFailed(_, e) => panic!("Render failed: {}", e),
+            RendererMessage::RenderCommand(..) => panic!("Renderer sent a command"),
+        }
+    }
+    panic!("Renderer thread stopped");
+}
+
+#[test]
+fn tiles_patched_at_their_offsets_give_the_image() {
+    let (sender, receiver) = renderer_thread();
+    let fp = FractalProperties {
+        center_x: -0.6,
+        max_iter: 50.0,
+        ..FractalProperties::default()
+    };
+    // Not a multiple of the tile size, so the last row and column of tiles are cut off
+    let (width, height) = (150, 100);
+    let answer = render(&sender, &receiver, 7, (width, height), fp);
+    assert!(answer.tiles.len() > 1);
+
+    let mut patched = vec![None; (width * height) as usize];
+    for (id, x, y, w, h, pixels) in answer.tiles {
+        assert_eq!(id, 7);
+        assert!(x + w <= width && y + h <= height);
+        assert_eq!(pixels.len(), (w * h) as usize);
+        for row in 0..h {
+            for col in 0..w {
+                let pixel = &mut patched[((y + row) * width + x + col) as usize];
+                assert!(pixel.is_none(), "Tiles overlap at {}, {}", x + col, y + row);
+                *pixel = Some(pixels[(row * w + col) as usize]);
+            }
+        }
+    }
+    let patched: Vec<_> = patched.into_iter().map(|p| p.unwrap()).collect();
+    assert_eq!(patched, answer.image);
+}
+
+#[test]
+fn progress_counts_up_to_every_pixel() {
+    let (sender, receiver) = renderer_thread();
+    let fp = FractalProperties {
+        max_iter: 50.0,
+        ..FractalProperties::default()
+    };
+    let answer = render(&sender, &receiver, 3, (150, 100), fp);
+    // One update per tile, counting up to all pixels and the iterations done for them
+    assert_eq!(answer.progress.len(), answer.tiles.len());
+    let mut completed: Vec<_> = answer.progress.iter().map(|p| p.1).collect();
+    completed.sort_unstable();
+    completed.dedup();
+    assert_eq!(completed.len(), answer.progress.len());
+    let last = answer.progress.iter().max_by_key(|p| p.1).unwrap();
+    assert_eq!(last.0, 3);
+    assert_eq!((last.1, last.2), (150 * 100, 150 * 100));
+    assert!(last.3 >= 150 * 100);
+}
+
+#[test]
+fn status_shows_throughput_and_time_left() {
+    let mut progress = RenderProgress::new(1, FractalProperties::default(), [2000, 2000]);
+    assert_eq!(progress.fraction(), 0.0);
+    assert_eq!(
+        progress.status_after(Duration::from_secs(1)),
+        "0.00 Mpix/s, 0.0 Miter/s, ETA: -"
+    );
+
+    progress.total_pixels = 4_000_000;
+    progress.completed_pixels = 1_000_000;
+    progress.iterations = 30_000_000;
+    assert_eq!(progress.fraction(), 0.25);
+    assert_eq!(
+        progress.status_after(Duration::from_secs(2)),
+        "0.50 Mpix/s, 15.0 Miter/s, ETA: 6.0s"
+    );
+
+    // More pixels than expected, e.g. counted twice, mustn't underflow
+    progress.completed_pixels = 5_000_000;
+    assert_eq!(progress.fraction(), 1.0);
+    assert_eq!(
+        progress.status_after(Duration::from_secs(2)),
+        "2.50 Mpix/s, 15.0 Miter/s, ETA: 0.0s"
+    );
+}
+
+/// `fp` moved so that the pixel at `(x, y)` of a `width` by `height` image is in the center
+fn panned(
+    fp: FractalProperties,
+    (width, height): (u32, u32),
+    (x, y): (Float, Float),
+) -> FractalProperties {
+    let (center_x, center_y) =
+        ViewTransform::new(&fp, width as Float, height as Float).to_complex(x, y);
+    FractalProperties {
+        center_x,
+        center_y,
+        ..fp
+    }
+}
+
+/// Iterations the last progress message of a render reported
+fn iterations(answer: &Answer) -> u64 {
+    answer.progress.iter().map(|p| p.3).max().unwrap()
+}
+
+#[test]
+fn pans_by_whole_pixels_match_full_renders() {
+    let size = (150, 100);
+    let fp = FractalProperties {
+        center_x: -0.6,
+        zoom: 1.3,
+        rotation: 20.0,
+        max_iter: 60.0,
+        ..FractalProperties::default()
+    };
+    let (full_sender, full_receiver) = renderer_thread();
+    for (dx, dy) in [(7, 0), (-7, 0), (0, 5), (0, -5), (-12, 9)] {
+        let (sender, receiver) = renderer_thread();
+        let first = render(&sender, &receiver, 1, size, fp);
+        let moved = panned(fp, size, (75.0 + dx as Float, 50.0 + dy as Float));
+        let shifted = render(&sender, &receiver, 2, size, moved);
+        let full = render(&full_sender, &full_receiver, 1, size, moved);
+
+        assert_eq!(shifted.image, full.image, "Shifted by {}, {}", dx, dy);
+        // Only the exposed strips were calculated
+        assert!(iterations(&shifted) < iterations(&first) / 2);
+    }
+}
+
+#[test]
+fn fractional_pans_render_everything() {
+    let size = (150, 100);
+    let fp = FractalProperties {
+        center_x: -0.6,
+        max_iter: 60.0,
+        ..FractalProperties::default()
+    };
+    let (sender, receiver) = renderer_thread();
+    render(&sender, &receiver, 1, size, fp);
+    // Off by more than the tolerance for rounding errors
+    let moved = panned(fp, size, (75.0 + 3.01, 50.0));
+    let answer = render(&sender, &receiver, 2, size, moved);
+
+    let (full_sender, full_receiver) = renderer_thread();
+    let full = render(&full_sender, &full_receiver, 1, size, moved);
+    assert_eq!(answer.image, full.image);
+    assert_eq!(iterations(&answer), iterations(&full));
+}
@@ -0,0 +1,105 @@
+use std::{
+    io::{Read, Write},
+    net::TcpStream,
+    thread,
+};
+
+use brot_rs::{
+    algorithms::mandelbrot::FractalProperties,
+    batch::Renderer,
+    server::{self, ServerSettings, TileCache},
+};
+
+/// Send a GET request and return the status line, the headers and the body
+fn get(port: u16, target: &str) -> (String, String, Vec<u8>) {
+    let request = format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", target);
+    send(port, request.as_bytes())
+}
+
+/// Send a raw request and return the status line, the headers and the body
+fn send(port: u16, request: &[u8]) -> (String, String, Vec<u8>) {
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+    stream.write_all(request).unwrap();
+    let mut response = vec![];
+    stream.read_to_end(&mut response).unwrap();
+    let split = response.windows(4).position(|w| w == b"\r\n\r\n").unwrap();
+    let head = String::from_utf8(response[..split].to_vec()).unwrap();
+    let (status, headers) = head.split_once("\r\n").unwrap();
+    (
+        status.to_string(),
+        headers.to_string(),
+        response[split + 4..].to_vec(),
+    )
+}
+
+#[test]
+fn cache_drops_the_least_recently_used_tile() {
+    let mut cache = TileCache::new(2);
+    cache.insert((0, 0, 0), vec![0].into());
+    cache.insert((1, 0, 0), vec![1].into());
+    cache.get((0, 0, 0));
+    cache.insert((1, 1, 0), vec![2].into());
+    assert_eq!(cache.len(), 2);
+    assert!(cache.get((1, 0, 0)).is_none());
+    assert_eq!(*cache.get((0, 0, 0)).unwrap(), [0]);
+}
+
+#[test]
+fn serves_renders_and_tiles() {
+    let listener = server::bind(0).unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let settings = ServerSettings {
+        tile_size: 32,
+        ..ServerSettings::default()
+    };
+    let fp = FractalProperties {
+        max_iter: 50.0,
+        ..FractalProperties::default()
+    };
+    thread::spawn(move || {
+        let algorithm = "cpu".parse().unwrap();
+        server::serve(
+            listener,
+            &settings,
+            fp,
+            &algorithm,
+            &mut Renderer::default(),
+        )
+    });
+
+    let (status, headers, body) = get(port, "/render?cx=-0.5&cy=0.1&zoom=2&w=40&h=30&iter=80");
+    assert_eq!(status, "HTTP/1.1 200 OK");
+    assert!(headers.contains("Content-Type: image/png"));
+    let img = image::load_from_memory(&body).unwrap();
+    assert_eq!((img.width(), img.height()), (40, 30));
+
+    let (status, headers, tile) = get(port, "/tiles/1/1/0.png");
+    assert_eq!(status, "HTTP/1.1 200 OK");
+    assert!(headers.contains("X-Cache: miss"));
+    let img = image::load_from_memory(&tile).unwrap();
+    assert_eq!((img.width(), img.height()), (32, 32));
+    let (_, headers, cached) = get(port, "/tiles/1/1/0.png");
+    assert!(headers.contains("X-Cache: hit"));
+    assert_eq!(cached, tile);
+
+    assert!(get(port, "/tiles/1/2/0.png").0.contains("404"));
+    assert!(get(port, "/tiles/64/0/0.png").0.contains("404"));
+    assert!(get(port, "/render?w=0").0.contains("400"));
+    assert!(get(port, "/render?zoom=abc").0.contains("400"));
+    assert!(get(port, "/render?zoom=NaN").0.contains("400"));
+    assert!(get(port, "/render?ss=1000&w=4096&h=4096").0.contains("400"));
+
+    // Requests that never end their line or headers are cut off. Exactly the limit is sent,
+    // so that the server reads all of it before answering.
+    let mut endless = b"GET /".to_vec();
+    endless.resize(8 * 1024, b'a');
+    assert!(send(port, &endless).0.contains("431"));
+    let mut headers = b"GET /render HTTP/1.1\r\n".to_vec();
+    while headers.len() < 8 * 1024 {
+        headers.extend(b"X-Padding: 0123456789\r\n");
+    }
+    headers.truncate(8 * 1024);
+    assert!(send(port, &headers).0.contains("431"));
+    assert!(send(port, b"GET /\xff HTTP/1.1\r\n\r\n").0.contains("400"));
+    assert!(send(port, b"\r\n\r\n").0.contains("400"));
+}
@@ -0,0 +1,73 @@
+use std::fs;
+
+use brot_rs::{
+    algorithms::mandelbrot::{FractalProperties, ViewTransform},
+    batch::Renderer,
+    export,
+    tiled::{self, TiledSettings},
+};
+
+mod common;
+
+#[test]
+fn tile_views_cover_the_image() {
+    let fp = FractalProperties {
+        center_x: -0.5,
+        center_y: 0.2,
+        zoom: 3.0,
+        rotation: 30.0,
+        ..FractalProperties::default()
+    };
+    let tile_fp = tiled::tile_properties(fp, 400, 300, (300, 0, 100, 100));
+    let whole = ViewTransform::new(&fp, 400.0, 300.0);
+    let tile = ViewTransform::new(&tile_fp, 100.0, 100.0);
+    for (x, y) in [(0.0, 0.0), (37.5, 80.0), (100.0, 100.0)] {
+        let (a, b) = (whole.to_complex(300.0 + x, y), tile.to_complex(x, y));
+        assert!((a.0 - b.0).abs() < 1e-12 && (a.1 - b.1).abs() < 1e-12);
+    }
+}
+
+#[test]
+fn tiled_render_matches_a_single_render() {
+    let dir = common::TempDir::new("tiled");
+    let fp = FractalProperties {
+        center_x: -0.6,
+        zoom: 0.8,
+        max_iter: 60.0,
+        ..FractalProperties::default()
+    };
+    let algorithm = "cpu".parse().unwrap();
+    let mut renderer = Renderer::default();
+    let expected: Vec<u8> = renderer.render(&algorithm, 50, 30, fp).unwrap().concat();
+
+    let mut decoded = vec![];
+    for name in ["poster.png", "poster.tiff"] {
+        let settings = TiledSettings {
+            output: dir.join(name),
+            width: 50,
+            height: 30,
+            tile_size: 16,
+        };
+        let bands = std::cell::Cell::new(0);
+        tiled::render_tiled(&settings, fp, &algorithm, &mut renderer, |_| {
+            bands.set(bands.get() + 1)
+        })
+        .unwrap();
+        assert_eq!(bands.get(), 2);
+        decoded.push(image::open(&settings.output).unwrap().to_rgb8().into_raw());
+    }
+    let location = export::read_png_location(dir.join("poster.png"));
+    let leftovers = fs::read_dir(&dir).unwrap().count();
+
+    assert_eq!(location.unwrap().properties, fp);
+    assert_eq!(leftovers, 2);
+    for pixels in decoded {
+        let differing = pixels
+            .iter()
+            .zip(&expected)
+            .filter(|(a, b)| a.abs_diff(**b) > 1)
+            .count();
+        assert_eq!(pixels.len(), expected.len());
+        assert_eq!(differing, 0);
+    }
+}
@@ -0,0 +1,142 @@
+use brot_rs::{
+    algorithms::mandelbrot::{Float, FractalProperties},
+    timeline::{Interpolation, Key, Property, Timeline, Track},
+    video::VideoSettings,
+};
+
+fn key(time: Float, value: Float, interpolation: Interpolation) -> Key {
+    Key {
+        time,
+        value,
+        interpolation,
+    }
+}
+
+fn timeline(property: Property, keys: &[Key]) -> Timeline {
+    let mut timeline = Timeline::default();
+    for k in keys {
+        timeline.set_key(property, *k);
+    }
+    timeline
+}
+
+fn approx(a: Float, b: Float) -> bool {
+    (a - b).abs() <= 1e-9 * b.abs().max(1.0)
+}
+
+#[test]
+fn interpolates_between_keys_and_holds_the_ends() {
+    let fp = FractalProperties::default();
+    for (interpolation, quarter) in [
+        (Interpolation::Linear, 2.5),
+        (Interpolation::Smoothstep, 10.0 * 0.15625),
+        (Interpolation::CatmullRom, 10.0 * 0.203125),
+    ] {
+        let timeline = timeline(
+            Property::ColorOffset,
+            &[key(1.0, 0.0, interpolation), key(5.0, 10.0, interpolation)],
+        );
+        let at = |time| timeline.properties_at(fp, time).color_offset;
+        assert!(approx(at(2.0), quarter), "{:?}: {}", interpolation, at(2.0));
+        assert!(approx(at(3.0), 5.0), "{:?}", interpolation);
+        assert_eq!(at(0.0), 0.0);
+        assert_eq!(at(9.0), 10.0);
+        assert_eq!(timeline.duration(), 5.0);
+    }
+}
+
+#[test]
+fn exponential_zoom_is_geometric_and_centers_follow_the_view() {
+    let mut timeline = Timeline::default();
+    let mut start = FractalProperties {
+        zoom: 1.0,
+        center_x: 0.0,
+        ..Default::default()
+    };
+    timeline.key_view(&start, 0.0, Interpolation::ExponentialZoom);
+    start.zoom = 100.0;
+    start.center_x = -1.0;
+    timeline.key_view(&start, 2.0, Interpolation::ExponentialZoom);
+
+    let half = timeline.properties_at(FractalProperties::default(), 1.0);
+    assert!(approx(half.zoom, 10.0));
+    // The view shrank from 1 to 0.1 of 1 to 0.01, so the center moved 0.9 / 0.99 of the way
+    assert!(approx(half.center_x, -0.9 / 0.99));
+}
+
+#[test]
+fn set_key_replaces_keys_at_the_same_time() {
+    let mut timeline = timeline(
+        Property::MaxIter,
+        &[
+            key(2.0, 300.0, Interpolation::Linear),
+            key(0.0, 100.0, Interpolation::Linear),
+            key(2.0, 500.4, Interpolation::Linear),
+        ],
+    );
+    let track = timeline.track(Property::MaxIter).unwrap();
+    assert_eq!(track.keys.len(), 2);
+    assert_eq!(track.keys[1].value, 500.4);
+    assert_eq!(
+        timeline
+            .properties_at(FractalProperties::default(), 2.0)
+            .max_iter,
+        500.0
+    );
+    assert!(timeline.track(Property::Zoom).is_none());
+    timeline.tracks.clear();
+    assert!(timeline.is_empty());
+}
+
+#[test]
+fn timelines_built_by_hand_are_checked() {
+    let empty = Track {
+        property: Property::Rotation,
+        keys: vec![],
+    };
+    assert_eq!(empty.value_at(1.0, None), None);
+
+    let mut timeline = Timeline {
+        tracks: vec![
+            empty,
+            Track {
+                property: Property::Skew,
+                keys: vec![
+                    key(2.0, 1.0, Interpolation::Linear),
+                    key(0.0, 0.0, Interpolation::Linear),
+                ],
+            },
+        ],
+    };
+    let mut settings = VideoSettings {
+        timeline: Some(timeline.clone()),
+        ..Default::default()
+    };
+    assert!(settings.validate().is_err());
+
+    timeline.sort_keys();
+    settings.timeline = Some(timeline.clone());
+    assert!(settings.validate().is_ok());
+    let fp = timeline.properties_at(FractalProperties::default(), 1.0);
+    assert_eq!((fp.skew, fp.rotation), (0.5, 0.0));
+}
+
+#[test]
+fn video_renders_the_timeline() {
+    let settings = VideoSettings {
+        fps: 2.0,
+        timeline: Some(timeline(
+            Property::Rotation,
+            &[
+                key(0.0, 0.0, Interpolation::Linear),
+                key(3.0, 90.0, Interpolation::Linear),
+            ],
+        )),
+        ..Default::default()
+    };
+    let fp = FractalProperties::default();
+    assert_eq!(settings.total_frames(), 6);
+    assert_eq!(settings.frame_properties(fp, 0).rotation, 0.0);
+    assert_eq!(settings.frame_properties(fp, 5).rotation, 90.0);
+    assert_eq!(settings.frame_properties(fp, 5).zoom, fp.zoom);
+}
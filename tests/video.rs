@@ -0,0 +1,180 @@
+use std::{fs, path::Path};
+
+use brot_rs::{
+    algorithms::mandelbrot::FractalProperties,
+    batch::Renderer,
+    reproject::{Keyframe, ZoomKeyframes},
+    video::{resume_video, Blend, VideoJob, VideoSettings, VideoWriter},
+};
+
+mod common;
+
+#[test]
+fn zooms_exponentially_from_start_to_end() {
+    let settings = VideoSettings {
+        fps: 25.0,
+        duration: 2.0,
+        start_zoom: 0.5,
+        end_zoom: 5e5,
+        ..VideoSettings::default()
+    };
+    assert_eq!(settings.total_frames(), 50);
+    assert_eq!(settings.zoom_at(0), 0.5);
+    assert_eq!(settings.zoom_at(49), 5e5);
+    let step = settings.zoom_step();
+    for frame in 1..49 {
+        let ratio = settings.zoom_at(frame) / settings.zoom_at(frame - 1);
+        assert!((ratio - step).abs() < 1e-9);
+    }
+}
+
+#[test]
+fn names_frames_from_the_pattern() {
+    let settings = VideoSettings {
+        output_dir: "out".into(),
+        file_pattern: "zoom-{frame}.bmp".to_string(),
+        ..VideoSettings::default()
+    };
+    assert_eq!(settings.frame_path(42), Path::new("out/zoom-00042.bmp"));
+    assert!(settings.validate().is_ok());
+
+    let no_placeholder = VideoSettings {
+        file_pattern: "zoom.png".to_string(),
+        ..settings
+    };
+    assert!(no_placeholder.validate().is_err());
+}
+
+#[test]
+fn reprojects_frames_from_the_covering_keyframe() {
+    let keyframes = ZoomKeyframes::new(0.5, 5.0);
+    assert_eq!(keyframes.count, 4);
+    assert_eq!(keyframes.index_of(0.5), 0);
+    assert_eq!(keyframes.index_of(1.9), 1);
+    assert_eq!(keyframes.index_of(2.0), 2);
+    assert_eq!(keyframes.index_of(5.0), 3);
+
+    // Left half black, right half white
+    let (width, height) = ZoomKeyframes::size(8, 4);
+    let keyframe = Keyframe {
+        index: 0,
+        zoom: 1.0,
+        width,
+        height,
+        pixels: (0..width * height)
+            .map(|i| {
+                if i % width < width / 2 {
+                    [0; 3]
+                } else {
+                    [255; 3]
+                }
+            })
+            .collect(),
+    };
+    // Same view, every frame pixel averages two keyframe pixels of the same colour
+    let frame = keyframe.reproject(8, 4, 1.0);
+    assert_eq!(frame[0], [0; 3]);
+    assert_eq!(frame[3], [0; 3]);
+    assert_eq!(frame[4], [255; 3]);
+    assert_eq!(frame[7], [255; 3]);
+    // Zoomed in 2x, the edge is filtered instead of jumping
+    let frame = keyframe.reproject(8, 4, 2.0);
+    assert_eq!(frame[0], [0; 3]);
+    assert_eq!(frame[7], [255; 3]);
+    assert!(frame[3][0] > 0 && frame[3][0] < 255);
+}
+
+#[test]
+fn spreads_motion_blur_samples_over_the_shutter() {
+    let settings = VideoSettings {
+        fps: 10.0,
+        duration: 1.0,
+        start_zoom: 1.0,
+        end_zoom: 1024.0,
+        motion_samples: 4,
+        shutter_angle: 360.0,
+        ..VideoSettings::default()
+    };
+    let fp = FractalProperties {
+        ss_factor: 4,
+        ..FractalProperties::default()
+    };
+    let frame = settings.frame_properties(fp, 5);
+    let samples = settings.sample_properties(fp, 5);
+    assert_eq!(samples.len(), 4);
+    // Centered on the frame, covering the time until the neighbouring frames
+    let ratio = samples[3].zoom / samples[0].zoom;
+    assert!((ratio - settings.zoom_step().powf(0.75)).abs() < 1e-9);
+    assert!((samples[1].zoom * samples[2].zoom / (frame.zoom * frame.zoom) - 1.0).abs() < 1e-9);
+    // The 16 samples per pixel are shared out among the sub-frames
+    assert!(samples.iter().all(|sample| sample.ss_factor == 2));
+    // The first and last frame only blend views within the zoom range
+    let last = settings.total_frames() - 1;
+    for frame in [0, last] {
+        let zooms = settings.sample_properties(fp, frame);
+        assert!(zooms.iter().all(|s| (1.0..=1024.0).contains(&s.zoom)));
+    }
+
+    let single = VideoSettings {
+        motion_samples: 1,
+        ..settings
+    };
+    assert_eq!(single.sample_properties(fp, 5), [frame]);
+
+    let mut blend = Blend::default();
+    blend.add(&[[0, 100, 255], [10, 10, 10]]);
+    blend.add(&[[255, 101, 255], [20, 20, 20]]);
+    assert_eq!(blend.finish(), [[128, 101, 255], [15, 15, 15]]);
+    blend.add(&[[1, 2, 3]]);
+    assert_eq!(blend.finish(), [[1, 2, 3]]);
+}
+
+#[test]
+fn resumes_an_interrupted_video() {
+    let dir = common::TempDir::new("resume");
+    let settings = VideoSettings {
+        output_dir: dir.to_path_buf(),
+        animation: Some(dir.join("video.gif")),
+        fps: 4.0,
+        duration: 1.0,
+        width: 8,
+        height: 6,
+        ..VideoSettings::default()
+    };
+    let fp = FractalProperties {
+        max_iter: 50.0,
+        ..FractalProperties::default()
+    };
+    let algorithm = "cpu".parse().unwrap();
+    let mut renderer = Renderer::default();
+
+    // Stop after two frames without finishing, as if the export crashed
+    let mut writer = VideoWriter::new(settings.clone(), fp, None).unwrap();
+    for frame in 0..2 {
+        let frame_fp = settings.frame_properties(fp, frame);
+        let img = renderer.render(&algorithm, 8, 6, frame_fp).unwrap();
+        writer.add_frame(frame, &img, frame_fp).unwrap();
+    }
+    drop(writer);
+    let job = VideoJob::read(VideoJob::path(&dir)).unwrap();
+    assert_eq!((job.completed_frames, job.fp), (2, fp));
+
+    let rendered = std::cell::RefCell::new(vec![]);
+    let resumed = resume_video(VideoJob::path(&dir), &algorithm, &mut renderer, |frame| {
+        rendered.borrow_mut().push(frame)
+    });
+    let job_left = VideoJob::path(&dir).exists();
+    let frames_on_disk = (0..4).all(|frame| settings.frame_path(frame).exists());
+    let mut decoder = gif::DecodeOptions::new()
+        .read_info(fs::File::open(dir.join("video.gif")).unwrap())
+        .unwrap();
+    let mut gif_frames = 0;
+    while decoder.read_next_frame().unwrap().is_some() {
+        gif_frames += 1;
+    }
+
+    resumed.unwrap();
+    assert_eq!(rendered.into_inner(), [2, 3]);
+    assert!(frames_on_disk && !job_left);
+    assert_eq!(gif_frames, 4);
+}